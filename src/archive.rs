@@ -0,0 +1,166 @@
+//! Read-only browsing inside `.zip`, `.tar`, `.tar.gz` and `.tgz` files.
+//! The whole member list is read once when the archive is entered; paths
+//! inside it are presented as `<archive path>/<member path>`, so the rest
+//! of the picker can navigate them like ordinary directories.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate, TimeZone};
+
+use crate::{Entry, EntrySource, Kind};
+
+struct Member {
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+pub struct Archive {
+    /// The archive file itself; every listed path is under it
+    pub path: PathBuf,
+    /// Keyed by path inside the archive, with implied parent directories
+    /// filled in
+    members: BTreeMap<PathBuf, Member>,
+}
+
+/// Whether `path` names an archive we know how to list.
+pub fn is_archive(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    [".zip", ".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self> {
+        let name = path.to_string_lossy().to_lowercase();
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let listed = if name.ends_with(".zip") {
+            list_zip(file)
+        } else if name.ends_with(".tar") {
+            list_tar(BufReader::new(file))
+        } else {
+            list_tar(flate2::read::GzDecoder::new(BufReader::new(file)))
+        }
+        .with_context(|| format!("reading archive {}", path.display()))?;
+
+        let mut members = BTreeMap::new();
+        for (inner, member) in listed {
+            // Archives needn't list directories explicitly
+            for dir in inner.ancestors().skip(1) {
+                if dir.as_os_str().is_empty() {
+                    break;
+                }
+                members.entry(dir.to_path_buf()).or_insert(Member {
+                    is_dir: true,
+                    size: 0,
+                    modified: None,
+                });
+            }
+            members.insert(inner, member);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            members,
+        })
+    }
+
+    /// Whether `path` is the archive or somewhere inside it.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.path)
+    }
+}
+
+impl EntrySource for Archive {
+    fn read_dir(&self, dir: &Path) -> Result<Vec<Entry>> {
+        let inner = dir
+            .strip_prefix(&self.path)
+            .with_context(|| format!("{} is outside the archive", dir.display()))?;
+        Ok(self
+            .members
+            .iter()
+            .filter(|(p, _)| p.parent() == Some(inner))
+            .map(|(p, m)| Entry {
+                name: p
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                path: self.path.join(p),
+                kind: if m.is_dir { Kind::Dir } else { Kind::File },
+                size: m.size,
+                modified: m.modified,
+                link: None,
+                mode: 0,
+                locked: false,
+                inode: None,
+                nlinks: None,
+                child_count: None,
+            })
+            .collect())
+    }
+}
+
+fn list_zip(file: File) -> Result<Vec<(PathBuf, Member)>> {
+    let mut zip = zip::ZipArchive::new(BufReader::new(file))?;
+    let mut out = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        // Raw access reads only the headers, never decompressing anything
+        let f = zip.by_index_raw(i)?;
+        let modified = f.last_modified().and_then(|t| {
+            let date = NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?;
+            let naive = date.and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())?;
+            Some(Local.from_local_datetime(&naive).single()?.into())
+        });
+        out.push((
+            clean(&f.name()?),
+            Member {
+                is_dir: f.is_dir(),
+                size: f.size(),
+                modified,
+            },
+        ));
+    }
+    Ok(out)
+}
+
+fn list_tar(reader: impl Read) -> Result<Vec<(PathBuf, Member)>> {
+    let mut tar = tar::Archive::new(reader);
+    let mut out = Vec::new();
+    for entry in tar.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let modified = header
+            .mtime()
+            .ok()
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        out.push((
+            clean(&entry.path()?.to_string_lossy()),
+            Member {
+                is_dir: header.entry_type().is_dir(),
+                size: header.size().unwrap_or(0),
+                modified,
+            },
+        ));
+    }
+    Ok(out)
+}
+
+/// Members are often stored as `./a/b` or `a/b/`; normalize to `a/b`.
+fn clean(name: &str) -> PathBuf {
+    Path::new(name)
+        .components()
+        .filter(|c| matches!(c, std::path::Component::Normal(_)))
+        .collect()
+}
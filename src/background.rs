@@ -0,0 +1,76 @@
+//! The thread behind every long-running job (size walks, flattening, grep,
+//! batch file operations): a worker closure that sends its results over a
+//! channel and checks a shared cancel flag as it goes. Each job module
+//! supplies the worker and keeps its own view of what has come back.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+};
+
+pub struct Job<T> {
+    rx: Receiver<T>,
+    cancel: Arc<AtomicBool>,
+    finished: bool,
+}
+
+impl<T: Send + 'static> Job<T> {
+    /// Run `work` on its own thread. It hands back results through the
+    /// function it's given and should stop soon after the flag is set.
+    pub fn spawn<F>(work: F) -> Self
+    where
+        F: FnOnce(&dyn Fn(T), &AtomicBool) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancel);
+        thread::spawn(move || {
+            // Nobody is listening any more once the job is dropped, and
+            // dropping it sets the flag, so a failed send can be ignored
+            work(&|item| drop(tx.send(item)), &flag);
+        });
+        Self {
+            rx,
+            cancel,
+            finished: false,
+        }
+    }
+}
+
+impl<T> Job<T> {
+    /// Take in whatever the worker has sent since the last call; called
+    /// every tick.
+    pub fn poll(&mut self) -> Vec<T> {
+        let mut items = Vec::new();
+        loop {
+            match self.rx.try_recv() {
+                Ok(item) => items.push(item),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        items
+    }
+
+    /// Whether the worker has returned and everything it sent was polled.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<T> Drop for Job<T> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
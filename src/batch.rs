@@ -0,0 +1,184 @@
+//! Deleting, copying and moving the marked set, one path at a time on a
+//! background thread. Each finished path is reported back so the UI can
+//! draw progress, and the cancel flag is checked between paths, so a
+//! cancelled batch leaves everything already processed done.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use crate::background;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Delete,
+    /// Copy into the destination directory
+    Copy,
+    /// Move into the destination directory
+    Move,
+}
+
+impl Op {
+    pub fn verb(self) -> &'static str {
+        match self {
+            Op::Delete => "deleting",
+            Op::Copy => "copying",
+            Op::Move => "moving",
+        }
+    }
+
+    pub fn past(self) -> &'static str {
+        match self {
+            Op::Delete => "deleted",
+            Op::Copy => "copied",
+            Op::Move => "moved",
+        }
+    }
+}
+
+/// Outcome for one source path: where it ended up (`None` once deleted),
+/// or why it failed.
+pub type ItemResult = Result<Option<PathBuf>, String>;
+
+pub struct Job {
+    pub op: Op,
+    pub total: usize,
+    /// Sources processed so far, with their outcome
+    pub results: Vec<(PathBuf, ItemResult)>,
+    pub finished: bool,
+    pub cancelled: bool,
+    worker: background::Job<(PathBuf, ItemResult)>,
+}
+
+impl Job {
+    /// `dest` is the target directory for copies and moves; unused for
+    /// deletes.
+    pub fn spawn(op: Op, paths: Vec<PathBuf>, dest: PathBuf) -> Self {
+        let total = paths.len();
+        let worker = background::Job::spawn(move |send, cancel| {
+            for src in paths {
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                let res = apply(op, &src, &dest).map_err(|e| e.to_string());
+                send((src, res));
+            }
+        });
+        Self {
+            op,
+            total,
+            results: Vec::new(),
+            finished: false,
+            cancelled: false,
+            worker,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        self.results.extend(self.worker.poll());
+        self.finished = self.worker.finished();
+    }
+
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+        self.worker.cancel();
+    }
+
+    pub fn failures(&self) -> usize {
+        self.results.iter().filter(|(_, r)| r.is_err()).count()
+    }
+}
+
+fn apply(op: Op, src: &Path, dest_dir: &Path) -> io::Result<Option<PathBuf>> {
+    if op == Op::Delete {
+        remove(src)?;
+        return Ok(None);
+    }
+    let name = src
+        .file_name()
+        .ok_or_else(|| io::Error::other("no file name"))?;
+    let dest = dest_dir.join(name);
+    if dest.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dest.display()),
+        ));
+    }
+    if op == Op::Move {
+        move_path(src, &dest)?;
+    } else if let Err(e) = copy_recursive(src, &dest) {
+        let _ = remove(&dest);
+        return Err(e);
+    }
+    Ok(Some(dest))
+}
+
+/// Delete a file, symlink or whole directory tree.
+pub fn remove(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Move `src` to `dest`, which mustn't exist yet. rename is atomic but
+/// can't cross filesystems, so only then does this copy and delete
+/// instead; any other rename error is returned as is.
+pub fn move_path(src: &Path, dest: &Path) -> io::Result<()> {
+    match fs::rename(src, dest) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        res => return res,
+    }
+    if let Err(e) = copy_recursive(src, dest) {
+        let _ = remove(dest);
+        return Err(e);
+    }
+    let src_is_dir = src.symlink_metadata()?.is_dir();
+    if let Err(e) = remove(src) {
+        // A file that couldn't be deleted is untouched, so its copy goes.
+        // A directory may be partly deleted by now, which leaves the copy
+        // as the only whole one
+        if !src_is_dir {
+            let _ = remove(dest);
+            return Err(e);
+        }
+        return Err(io::Error::new(
+            e.kind(),
+            format!("{e}; its copy is kept at {}", dest.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Copy a file, symlink (as a link) or whole directory tree.
+pub fn copy_recursive(src: &Path, dest: &Path) -> io::Result<()> {
+    let md = src.symlink_metadata()?;
+    if md.file_type().is_symlink() {
+        copy_symlink(src, dest)
+    } else if md.is_dir() {
+        if dest.starts_with(src) {
+            return Err(io::Error::other("can't copy a directory into itself"));
+        }
+        fs::create_dir(dest)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(src, dest).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dest: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dest)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::copy(src, dest).map(|_| ())
+}
@@ -0,0 +1,70 @@
+//! Command-line flags. Parsed by hand; there are few enough of them.
+
+use std::{env, path::PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+const USAGE: &str = "\
+usage: simple-file-picker-ratatui-rust [options]
+
+options:
+  --root <dir>      never navigate above <dir>
+  --select <name>   start with the entry called <name> highlighted
+  --pick-dirs       Enter marks directories instead of entering them
+                    (l or Right still navigates)
+  --single          Enter on a file prints its path and exits
+  --force           skip the confirmation before deleting or overwriting
+                    (toggled with ! while running)
+  --no-alt-screen   draw on the normal screen, leaving the last frame
+                    visible afterwards (handy for debugging)
+  -h, --help        show this help
+
+exit status:
+  0    accepted with Q (or Enter with --single); paths on stdout
+  130  cancelled with q, Esc or Ctrl-c; nothing printed (cancel_exit_code
+       in the config changes it)
+  1    error";
+
+#[derive(Default)]
+pub struct Args {
+    /// Confine navigation to this directory and below
+    pub root: Option<PathBuf>,
+    /// Name of the entry to highlight in the start directory
+    pub select: Option<String>,
+    /// Enter marks directories; navigation moves to l/Right
+    pub pick_dirs: bool,
+    /// Pick one file with Enter and exit, like a file chooser dialog
+    pub single: bool,
+    /// Skip the alternate screen
+    pub no_alt_screen: bool,
+    /// Start with destructive confirmations skipped
+    pub force: bool,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self> {
+        let mut args = Self::default();
+        let mut it = env::args().skip(1);
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--root" => {
+                    let dir = it.next().context("--root needs a directory")?;
+                    args.root = Some(PathBuf::from(dir));
+                }
+                "--select" => {
+                    args.select = Some(it.next().context("--select needs a name")?);
+                }
+                "--pick-dirs" => args.pick_dirs = true,
+                "--single" => args.single = true,
+                "--no-alt-screen" => args.no_alt_screen = true,
+                "--force" => args.force = true,
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                other => bail!("unknown argument: {other}\n\n{USAGE}"),
+            }
+        }
+        Ok(args)
+    }
+}
@@ -0,0 +1,75 @@
+//! Copying text to the system clipboard by piping it into whichever
+//! clipboard tool is installed, with an OSC 52 terminal escape as the last
+//! resort (which also works over SSH in terminals that support it).
+
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use anyhow::Result;
+
+/// Tried in order; the first one that runs successfully wins
+const TOOLS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+    ("clip.exe", &[]),
+];
+
+/// Copy `data` to the clipboard, returning the name of the mechanism used.
+pub fn copy(data: &[u8]) -> Result<&'static str> {
+    for (tool, args) in TOOLS {
+        if pipe_to(tool, args, data).is_ok() {
+            return Ok(tool);
+        }
+    }
+    osc52(data)?;
+    Ok("OSC 52")
+}
+
+fn pipe_to(tool: &str, args: &[&str], data: &[u8]) -> io::Result<()> {
+    let mut child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data)?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{tool} exited with {status}")))
+    }
+}
+
+fn osc52(data: &[u8]) -> io::Result<()> {
+    let mut out = crate::term::output()?;
+    write!(out, "\x1b]52;c;{}\x07", base64(data))?;
+    out.flush()
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,259 @@
+//! Minimal `key = value` configuration, read once at startup from
+//! `$XDG_CONFIG_HOME/simple-file-picker/config` (or `~/.config/...`).
+//!
+//! Lines starting with `#` are comments. Unknown keys and values that fail
+//! to parse are ignored so a stale config never prevents startup; the few
+//! whose fallback would be puzzling also leave a warning in `warnings`.
+
+use std::{env, fs, path::PathBuf, str::FromStr};
+
+use chrono::format::{Item, StrftimeItems};
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::Density;
+
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+#[derive(Clone)]
+pub struct Config {
+    /// Re-read the listing every this many seconds, starting out on; 0
+    /// starts with it off. Ctrl-w toggles it either way.
+    pub auto_refresh_secs: u64,
+    /// Exit status when cancelling with `q`, Esc or Ctrl-c
+    pub cancel_exit_code: i32,
+    /// Draw executable files in green (Unix only)
+    pub color_executables: bool,
+    /// Deleting this many entries or fewer goes ahead without asking; 0
+    /// always asks
+    pub confirm_delete_above: usize,
+    /// Ask before opening files that live on a network filesystem (Linux only)
+    pub confirm_network_open: bool,
+    /// strftime-style format for absolute times in the list, quoted if it
+    /// has leading or trailing spaces
+    pub date_format: String,
+    /// Initial list density: `dense`, `normal` or `comfortable`
+    pub density: Density,
+    /// Programs tried in order when `$EDITOR`/`$PAGER` is unset or fails,
+    /// as a comma-separated list
+    pub fallback_openers: Vec<String>,
+    /// Glob patterns (`*`, `?`) for names that are always left out of the
+    /// listing, as a comma-separated list
+    pub hide_patterns: Vec<String>,
+    /// Drawn in front of the highlighted entry; quote it to keep trailing
+    /// spaces, e.g. `highlight_symbol = "> "`
+    pub highlight_symbol: String,
+    /// From `highlight_fg`/`highlight_bg` (color names, `#rrggbb` or a
+    /// 0-255 index) and `highlight_modifiers` (e.g. `bold, italic`)
+    pub highlight_style: Style,
+    /// Named directories for the Ctrl-b sidebar, as comma-separated
+    /// `Name=path` pairs with `~` expanded. Defaults to just home.
+    pub places: Vec<(String, PathBuf)>,
+    /// Percent of the width the list keeps when the preview pane is open
+    pub preview_split: u16,
+    /// Try listing every subdirectory up front and draw the ones we can't
+    /// enter with a lock; costs an extra open per directory
+    pub probe_dir_access: bool,
+    /// Show the free and total space of the filesystem holding cwd in the
+    /// footer
+    pub show_disk_space: bool,
+    /// Show the git branch, with `*` when there are uncommitted changes, in
+    /// the header while inside a repository
+    pub show_git: bool,
+    /// Shell command that reads entry names on stdin and prints them back in
+    /// the desired order, e.g. `sort -V`; adds a "command" sort mode
+    pub sort_command: Option<String>,
+    /// Where Ctrl-n finds templates for new files; `~/` is expanded.
+    /// Defaults to `templates` next to this file.
+    pub template_dir: Option<PathBuf>,
+    /// Problems with the file worth showing on startup
+    pub warnings: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auto_refresh_secs: 0,
+            cancel_exit_code: 130,
+            color_executables: true,
+            confirm_delete_above: 0,
+            confirm_network_open: true,
+            date_format: DEFAULT_DATE_FORMAT.to_string(),
+            density: Density::Normal,
+            fallback_openers: vec!["less".to_string(), "vi".to_string()],
+            hide_patterns: Vec::new(),
+            highlight_symbol: "➤ ".to_string(),
+            highlight_style: Style::default().bg(Color::Gray).fg(Color::Black),
+            places: env::var_os("HOME")
+                .map(|home| ("Home".to_string(), PathBuf::from(home)))
+                .into_iter()
+                .collect(),
+            preview_split: 50,
+            probe_dir_access: false,
+            show_disk_space: false,
+            show_git: true,
+            sort_command: None,
+            template_dir: config_dir().map(|d| d.join("templates")),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        config_dir()
+            .map(|d| d.join("config"))
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut cfg = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                cfg.set(key.trim(), value.trim());
+            }
+        }
+        cfg
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "auto_refresh_secs" => {
+                if let Ok(v) = value.parse() {
+                    self.auto_refresh_secs = v;
+                }
+            }
+            "cancel_exit_code" => {
+                if let Ok(v) = value.parse() {
+                    self.cancel_exit_code = v;
+                }
+            }
+            "color_executables" => set_bool(&mut self.color_executables, value),
+            "confirm_delete_above" => {
+                if let Ok(v) = value.parse() {
+                    self.confirm_delete_above = v;
+                }
+            }
+            "confirm_network_open" => set_bool(&mut self.confirm_network_open, value),
+            "date_format" => {
+                let value = unquote(value);
+                if StrftimeItems::new(value).any(|item| item == Item::Error) {
+                    self.warnings.push(format!(
+                        "config: bad date_format {value:?}, using {DEFAULT_DATE_FORMAT:?}"
+                    ));
+                } else {
+                    self.date_format = value.to_string();
+                }
+            }
+            "density" => match value {
+                "dense" => self.density = Density::Dense,
+                "normal" => self.density = Density::Normal,
+                "comfortable" => self.density = Density::Comfortable,
+                _ => {}
+            },
+            "fallback_openers" => self.fallback_openers = parse_list(value),
+            "hide_patterns" => self.hide_patterns = parse_list(value),
+            "places" => {
+                self.places.clear();
+                for item in parse_list(value) {
+                    match item.split_once('=') {
+                        Some((name, path)) => self
+                            .places
+                            .push((name.trim().to_string(), expand_home(path.trim()))),
+                        None => self
+                            .warnings
+                            .push(format!("config: places entry {item:?} isn't Name=path")),
+                    }
+                }
+            }
+            "preview_split" => {
+                if let Ok(v) = value.parse() {
+                    self.preview_split = v;
+                }
+            }
+            "probe_dir_access" => set_bool(&mut self.probe_dir_access, value),
+            "show_disk_space" => set_bool(&mut self.show_disk_space, value),
+            "show_git" => set_bool(&mut self.show_git, value),
+            "sort_command" => self.sort_command = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "template_dir" => self.template_dir = Some(expand_home(unquote(value))),
+            "highlight_symbol" => self.highlight_symbol = unquote(value).to_string(),
+            "highlight_fg" => {
+                if let Ok(c) = Color::from_str(value) {
+                    self.highlight_style = self.highlight_style.fg(c);
+                }
+            }
+            "highlight_bg" => {
+                if let Ok(c) = Color::from_str(value) {
+                    self.highlight_style = self.highlight_style.bg(c);
+                }
+            }
+            "highlight_modifiers" => {
+                let mods = parse_list(value)
+                    .iter()
+                    .filter_map(|m| parse_modifier(m))
+                    .fold(Modifier::empty(), |acc, m| acc | m);
+                self.highlight_style = self.highlight_style.add_modifier(mods);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(base.join("simple-file-picker"))
+}
+
+pub fn expand_home(value: &str) -> PathBuf {
+    let rest = if value == "~" {
+        Some("")
+    } else {
+        value.strip_prefix("~/")
+    };
+    match (rest, env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(value),
+    }
+}
+
+fn set_bool(slot: &mut bool, value: &str) {
+    match value {
+        "true" | "yes" | "on" | "1" => *slot = true,
+        "false" | "no" | "off" | "0" => *slot = false,
+        _ => {}
+    }
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn parse_modifier(name: &str) -> Option<Modifier> {
+    Some(match name {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" | "underline" => Modifier::UNDERLINED,
+        "reversed" | "reverse" => Modifier::REVERSED,
+        "crossed_out" => Modifier::CROSSED_OUT,
+        _ => return None,
+    })
+}
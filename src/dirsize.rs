@@ -0,0 +1,80 @@
+//! Recursive directory sizes, summed on a background thread. The walker
+//! streams running totals back so the UI can show progress on huge trees,
+//! and checks a cancel flag between entries.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::background;
+
+/// Send a running total after this many files
+const REPORT_EVERY: u64 = 500;
+
+#[derive(Clone, Copy, Default)]
+pub struct Totals {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+pub struct Job {
+    pub dir: PathBuf,
+    pub totals: Totals,
+    pub done: bool,
+    /// Sends running totals, the last one once the walk is over
+    worker: background::Job<Totals>,
+}
+
+impl Job {
+    pub fn spawn(dir: PathBuf) -> Self {
+        let root = dir.clone();
+        let worker = background::Job::spawn(move |send, cancel| {
+            let mut totals = Totals::default();
+            walk(&root, &mut totals, send, cancel);
+            send(totals);
+        });
+        Self {
+            dir,
+            totals: Totals::default(),
+            done: false,
+            worker,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        if let Some(t) = self.worker.poll().pop() {
+            self.totals = t;
+        }
+        self.done = self.worker.finished();
+    }
+
+    pub fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
+
+fn walk(dir: &Path, totals: &mut Totals, send: &dyn Fn(Totals), cancel: &AtomicBool) {
+    let Ok(rd) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in rd.flatten() {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        // Symlinks are counted as themselves, never followed
+        let Ok(md) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        if md.is_dir() {
+            walk(&entry.path(), totals, send, cancel);
+        } else {
+            totals.bytes += md.len();
+            totals.files += 1;
+            if totals.files.is_multiple_of(REPORT_EVERY) {
+                send(*totals);
+            }
+        }
+    }
+}
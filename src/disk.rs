@@ -0,0 +1,38 @@
+//! Free and total space on the filesystem holding a directory, for the
+//! footer. Read with `statvfs`, so there's nothing to show off Unix.
+
+use std::path::Path;
+
+pub struct Space {
+    /// Bytes available to unprivileged users, leaving out the root reserve
+    pub free: u64,
+    pub total: u64,
+}
+
+#[cfg(unix)]
+// The statvfs fields are u64 on Linux but narrower on some other Unixes
+#[allow(clippy::useless_conversion)]
+pub fn space(dir: &Path) -> Option<Space> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut st = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `st` is only read once statvfs
+    // has filled it in
+    let st = unsafe {
+        if libc::statvfs(path.as_ptr(), st.as_mut_ptr()) != 0 {
+            return None;
+        }
+        st.assume_init()
+    };
+    let block = u64::from(st.f_frsize);
+    Some(Space {
+        free: u64::from(st.f_bavail) * block,
+        total: u64::from(st.f_blocks) * block,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn space(_dir: &Path) -> Option<Space> {
+    None
+}
@@ -0,0 +1,116 @@
+//! The flattened listing: every file under a directory. On disk the walk
+//! runs on a background thread that checks a cancel flag between
+//! directories and reports how many files it has found so far; archives
+//! are already in memory, so they're walked in place.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{Entry, EntrySource, FsSource, background};
+
+/// How far below the root the walk looks
+pub const MAX_DEPTH: usize = 8;
+
+/// Send a running count after this many directories
+const REPORT_EVERY: usize = 100;
+
+/// Which names to leave out, along with anything under them
+pub type Skip = Box<dyn Fn(&str) -> bool + Send>;
+
+enum Msg {
+    Progress(usize),
+    Done(Vec<Entry>),
+}
+
+pub struct Job {
+    pub root: PathBuf,
+    /// Files found so far
+    pub found: usize,
+    /// The listing, once the walk is over
+    pub files: Vec<Entry>,
+    pub done: bool,
+    worker: background::Job<Msg>,
+}
+
+impl Job {
+    pub fn spawn(source: FsSource, root: PathBuf, skip: Skip) -> Self {
+        let dir = root.clone();
+        let worker = background::Job::spawn(move |send, cancel| {
+            let files = walk(&source, &dir, &*skip, cancel, &mut |found| {
+                send(Msg::Progress(found))
+            });
+            if !cancel.load(Ordering::Relaxed) {
+                send(Msg::Done(files));
+            }
+        });
+        Self {
+            root,
+            found: 0,
+            files: Vec::new(),
+            done: false,
+            worker,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        for msg in self.worker.poll() {
+            match msg {
+                Msg::Progress(found) => self.found = found,
+                Msg::Done(files) => {
+                    self.found = files.len();
+                    self.files = files;
+                }
+            }
+        }
+        self.done = self.worker.finished();
+    }
+
+    pub fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
+
+/// Every file under `root` down to `MAX_DEPTH` levels, named by its path
+/// relative to `root`. Unreadable directories are skipped, symlinked ones
+/// aren't followed (so link cycles can't recurse), and names for which
+/// `skip` is true are left out along with anything under them. Stops
+/// early once `cancel` is set; `progress` hears the running count every
+/// so often.
+pub fn walk(
+    source: &dyn EntrySource,
+    root: &Path,
+    skip: &dyn Fn(&str) -> bool,
+    cancel: &AtomicBool,
+    progress: &mut dyn FnMut(usize),
+) -> Vec<Entry> {
+    let mut files = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+    let mut walked: usize = 0;
+    while let Some((dir, depth)) = pending.pop() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        walked += 1;
+        if walked.is_multiple_of(REPORT_EVERY) {
+            progress(files.len());
+        }
+        let Ok(entries) = source.read_dir(&dir) else {
+            continue;
+        };
+        for mut e in entries.into_iter().filter(|e| !skip(&e.name)) {
+            if e.is_dir() {
+                if e.link.is_none() && depth + 1 < MAX_DEPTH {
+                    pending.push((e.path, depth + 1));
+                }
+                continue;
+            }
+            if let Ok(rel) = e.path.strip_prefix(root) {
+                e.name = rel.to_string_lossy().into_owned();
+            }
+            files.push(e);
+        }
+    }
+    files
+}
@@ -0,0 +1,60 @@
+//! The branch and dirty state of the repository around a directory, for the
+//! header. Read by running `git` itself, so nothing is shown when it isn't
+//! installed or the directory isn't in a work tree.
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+pub struct RepoState {
+    /// Branch name, or a short commit id when HEAD is detached
+    pub branch: String,
+    /// Tracked files with uncommitted changes; untracked ones don't count
+    pub dirty: bool,
+}
+
+impl RepoState {
+    /// `main`, or `main*` with uncommitted changes.
+    pub fn label(&self) -> String {
+        format!("{}{}", self.branch, if self.dirty { "*" } else { "" })
+    }
+}
+
+/// The state of the repository `dir` is in, if any.
+pub fn state(dir: &Path) -> Option<RepoState> {
+    // One call answers both: `# branch.*` headers, then a line per change
+    let output = Command::new("git")
+        .args([
+            "status",
+            "--porcelain=v2",
+            "--branch",
+            "--untracked-files=no",
+        ])
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut head = None;
+    let mut oid = None;
+    let mut dirty = false;
+    for line in text.lines() {
+        if let Some(name) = line.strip_prefix("# branch.head ") {
+            head = Some(name);
+        } else if let Some(id) = line.strip_prefix("# branch.oid ") {
+            oid = Some(id);
+        } else if !line.starts_with('#') {
+            dirty = true;
+        }
+    }
+    let branch = match (head?, oid) {
+        ("(detached)", Some(id)) => id.chars().take(7).collect(),
+        (name, _) => name.to_string(),
+    };
+    Some(RepoState { branch, dirty })
+}
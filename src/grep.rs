@@ -0,0 +1,106 @@
+//! Content search: which of a set of files contain a query, how often, and
+//! where first. Files are read directly rather than by shelling out, since
+//! neither grep's flags nor ripgrep's presence can be counted on. The search
+//! runs on a background thread that checks a cancel flag between files.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use crate::{background, preview};
+
+/// Files bigger than this are skipped rather than read whole
+const MAX_FILE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Snippets are cut to this many chars
+const SNIPPET_CHARS: usize = 120;
+
+pub struct Hit {
+    pub path: PathBuf,
+    /// Number of matching lines
+    pub count: usize,
+    /// 1-based line and column of the first match
+    pub line: usize,
+    pub column: usize,
+    /// The first matching line, trimmed
+    pub snippet: String,
+}
+
+pub struct Job {
+    pub query: String,
+    /// Matches so far, in the order of the paths given
+    pub hits: Vec<Hit>,
+    pub searched: usize,
+    pub total: usize,
+    pub done: bool,
+    /// Sends what each file matched, one file at a time
+    worker: background::Job<Option<Hit>>,
+}
+
+impl Job {
+    /// Case-insensitive search for `query` in each of `paths`, in order.
+    /// Files that can't be read, are too big or look binary are skipped.
+    pub fn spawn(paths: Vec<PathBuf>, query: &str) -> Self {
+        let total = paths.len();
+        let needle = query.to_lowercase();
+        let worker = background::Job::spawn(move |send, cancel| {
+            for path in paths {
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                send(search_file(&path, &needle));
+            }
+        });
+        Self {
+            query: query.to_string(),
+            hits: Vec::new(),
+            searched: 0,
+            total,
+            done: false,
+            worker,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        for hit in self.worker.poll() {
+            self.searched += 1;
+            self.hits.extend(hit);
+        }
+        self.done = self.worker.finished();
+    }
+
+    pub fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
+
+fn search_file(path: &Path, needle: &str) -> Option<Hit> {
+    let md = fs::metadata(path).ok()?;
+    if !md.is_file() || md.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if preview::looks_binary(&bytes) {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    let mut first = None;
+    let mut count = 0;
+    for (i, line) in text.lines().enumerate() {
+        let lower = line.to_lowercase();
+        if let Some(at) = lower.find(needle) {
+            count += 1;
+            first.get_or_insert((i + 1, lower[..at].chars().count() + 1, line));
+        }
+    }
+    let (line, column, snippet) = first?;
+    Some(Hit {
+        path: path.to_path_buf(),
+        count,
+        line,
+        column,
+        snippet: snippet.trim().chars().take(SNIPPET_CHARS).collect(),
+    })
+}
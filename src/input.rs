@@ -0,0 +1,106 @@
+//! Single-line text editing shared by every prompt: the live filter and
+//! search, and the prompts that submit on Enter.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Default)]
+pub struct TextInput {
+    value: String,
+    /// Byte offset into `value`, always on a char boundary
+    cursor: usize,
+}
+
+impl TextInput {
+    /// Start out holding `initial`, with the cursor at the end.
+    pub fn new(initial: &str) -> Self {
+        Self {
+            value: initial.to_string(),
+            cursor: initial.len(),
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.value.clear();
+        self.cursor = 0;
+    }
+
+    /// Insert `text` at the cursor, as if typed. Line breaks become spaces,
+    /// since the input is a single line.
+    pub fn insert_str(&mut self, text: &str) {
+        let text = text.replace(['\r', '\n'], " ");
+        self.value.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    /// The text split at the cursor, for drawing it.
+    pub fn split_at_cursor(&self) -> (&str, &str) {
+        self.value.split_at(self.cursor)
+    }
+
+    /// Apply an editing key. Returns false for keys that aren't editing
+    /// keys (Enter, Esc, ...), which are the caller's to interpret.
+    ///
+    /// Besides plain typing and Backspace/Delete, this understands the
+    /// usual readline motions: Left/Right, Home/End or Ctrl-a/Ctrl-e,
+    /// Ctrl-w to delete the word before the cursor and Ctrl-k to delete to
+    /// the end of the line.
+    pub fn handle(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = self.value.len(),
+            KeyCode::Char('w') if ctrl => {
+                let start = self.word_start();
+                self.value.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            KeyCode::Char('k') if ctrl => self.value.truncate(self.cursor),
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                self.value.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            KeyCode::Backspace => {
+                if let Some(c) = self.value[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                    self.value.remove(self.cursor);
+                }
+            }
+            KeyCode::Delete => {
+                if self.cursor < self.value.len() {
+                    self.value.remove(self.cursor);
+                }
+            }
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.value.len(),
+            KeyCode::Left => {
+                if let Some(c) = self.value[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                }
+            }
+            KeyCode::Right => {
+                if let Some(c) = self.value[self.cursor..].chars().next() {
+                    self.cursor += c.len_utf8();
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Where Ctrl-w deletes back to: past any spaces before the cursor,
+    /// then to the start of the word before them.
+    fn word_start(&self) -> usize {
+        let before = self.value[..self.cursor].trim_end();
+        before.rfind(char::is_whitespace).map_or(0, |i| {
+            i + before[i..].chars().next().map_or(1, char::len_utf8)
+        })
+    }
+}
@@ -1,62 +1,1058 @@
 use std::{
     cmp::Ordering,
-    collections::HashSet,
-    env, fs, io,
-    path::{Path, PathBuf},
-    process::Command,
-    time::Duration,
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::atomic::AtomicBool,
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, TimeZone};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
-    execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{
     prelude::*,
     widgets::{block::Title, *},
 };
+use regex::{Regex, RegexBuilder};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+mod archive;
+mod background;
+mod batch;
+mod cli;
+mod clipboard;
+mod config;
+mod dirsize;
+mod disk;
+mod flatten;
+mod git;
+mod grep;
+mod input;
+mod netfs;
+mod preview;
+mod recent;
+mod term;
+
+use cli::Args;
+use config::Config;
+use input::TextInput;
 
 #[derive(Clone)]
 struct Entry {
     name: String,
     path: PathBuf,
-    is_dir: bool,
+    kind: Kind,
+    size: u64,
+    modified: Option<SystemTime>,
+    /// Set for symlinks; the other fields then describe the target, or the
+    /// link itself if it is broken
+    link: Option<LinkStatus>,
+    /// Unix mode bits (file type and permissions); always 0 elsewhere
+    mode: u32,
+    /// A directory we found we can't list, when `probe_dir_access` is on
+    locked: bool,
+    /// Inode number and hard link count; Unix only
+    inode: Option<u64>,
+    nlinks: Option<u64>,
+    /// How many entries a directory holds, when counting and readable
+    child_count: Option<usize>,
+}
+
+impl Entry {
+    fn is_dir(&self) -> bool {
+        self.kind == Kind::Dir
+    }
+
+    fn is_executable(&self) -> bool {
+        // A broken link's own mode is always rwxrwxrwx, which says nothing
+        self.kind == Kind::File && self.link != Some(LinkStatus::Broken) && self.mode & 0o111 != 0
+    }
+}
+
+/// What an entry is, after following symlinks.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    File,
+    Dir,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl Kind {
+    #[cfg(unix)]
+    fn of(ft: fs::FileType) -> Self {
+        use std::os::unix::fs::FileTypeExt;
+        if ft.is_dir() {
+            Kind::Dir
+        } else if ft.is_fifo() {
+            Kind::Fifo
+        } else if ft.is_socket() {
+            Kind::Socket
+        } else if ft.is_block_device() {
+            Kind::BlockDevice
+        } else if ft.is_char_device() {
+            Kind::CharDevice
+        } else {
+            Kind::File
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn of(ft: fs::FileType) -> Self {
+        if ft.is_dir() { Kind::Dir } else { Kind::File }
+    }
+
+    /// Not something an editor or pager can sensibly open
+    fn is_special(self) -> bool {
+        !matches!(self, Kind::File | Kind::Dir)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Kind::File => "file",
+            Kind::Dir => "directory",
+            Kind::Fifo => "FIFO",
+            Kind::Socket => "socket",
+            Kind::BlockDevice => "block device",
+            Kind::CharDevice => "character device",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LinkStatus {
+    Valid,
+    /// The target doesn't exist (dangling link)
+    Broken,
+}
+
+/// Ordering of entries, normally within the dirs-first grouping.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortMode {
+    /// Case-insensitive name, A to Z
+    Name,
+    /// Largest first
+    Size,
+    /// Newest first
+    Modified,
+    /// Newest first with directories and files mixed together, for "what
+    /// did I just change"
+    Touched,
+    /// Whatever order the configured `sort_command` prints
+    Command,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Size,
+            SortMode::Size => SortMode::Modified,
+            SortMode::Modified => SortMode::Touched,
+            SortMode::Touched => SortMode::Command,
+            SortMode::Command => SortMode::Name,
+        }
+    }
+
+    /// Whether directories are kept ahead of files.
+    fn groups_dirs(self) -> bool {
+        self != SortMode::Touched
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Size => "size",
+            SortMode::Modified => "modified",
+            SortMode::Touched => "modified, dirs mixed in",
+            SortMode::Command => "command",
+        }
+    }
+}
+
+/// How much room each list row takes up.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Density {
+    /// Bare names, no marker circle or icon
+    Dense,
+    Normal,
+    /// Icons plus a blank line between entries
+    Comfortable,
+}
+
+impl Density {
+    fn next(self) -> Self {
+        match self {
+            Density::Dense => Density::Normal,
+            Density::Normal => Density::Comfortable,
+            Density::Comfortable => Density::Dense,
+        }
+    }
+}
+
+/// The modified-time column, cycled with `T`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TimeColumn {
+    Hidden,
+    /// e.g. `3d ago`
+    Relative,
+    /// In `date_format`, e.g. `2024-01-15 14:30`
+    Absolute,
+}
+
+impl TimeColumn {
+    fn next(self) -> Self {
+        match self {
+            TimeColumn::Hidden => TimeColumn::Relative,
+            TimeColumn::Relative => TimeColumn::Absolute,
+            TimeColumn::Absolute => TimeColumn::Hidden,
+        }
+    }
+
+    /// Widest text the column can hold. Absolute times are measured on a
+    /// Wednesday in September, the longest English day and month names.
+    fn width(self, date_format: &str) -> usize {
+        match self {
+            TimeColumn::Hidden => 0,
+            TimeColumn::Relative => "11mo ago".len(),
+            TimeColumn::Absolute => Local
+                .with_ymd_and_hms(2024, 9, 25, 23, 59, 59)
+                .single()
+                .map_or(0, |t| t.format(date_format).to_string().width()),
+        }
+    }
 }
 
 struct App {
+    config: Config,
+    /// Where the picker was launched; base for relative paths
+    start_dir: PathBuf,
     cwd: PathBuf,
+    /// Everything in `cwd`; `entries` is the filtered, sorted view of it
+    all_entries: Vec<Entry>,
     entries: Vec<Entry>,
     list_state: ListState,
     selected_paths: HashSet<PathBuf>,
+    /// Sizes of marked files, cached at mark time so the total survives
+    /// navigating away from their directory
+    marked_sizes: HashMap<PathBuf, u64>,
+    /// Which mark register `selected_paths` and `marked_sizes` belong to;
+    /// the others are parked in `registers`
+    register: char,
+    registers: HashMap<char, (HashSet<PathBuf>, HashMap<PathBuf, u64>)>,
+    /// `"` was pressed; the next key names a register
+    awaiting_register: bool,
+    /// Digits typed ahead of a key, vi-style; Backspace climbs that many
+    /// levels
+    count: Option<usize>,
+    show_ext_column: bool,
+    status: Option<Status>,
+    group_marked: bool,
+    sort_mode: SortMode,
+    density: Density,
+    /// Yes/no question waiting on an answer; blocks other input
+    confirm: Option<Confirm>,
+    /// Batch operation in progress; input is blocked except for Esc
+    batch: Option<batch::Job>,
+    /// What `Ctrl-z` reverses
+    undo: Option<UndoOp>,
+    /// Text prompt waiting for Enter; blocks other input
+    prompt: Option<Prompt>,
+    /// Substring filter applied to the listing
+    filter: TextInput,
+    /// Whether keystrokes currently go into `filter`
+    filtering: bool,
+    /// Match the filter exactly rather than ignoring case; Alt-c flips it
+    filter_case_sensitive: bool,
+    /// Read the filter as a regular expression; Alt-r flips it
+    filter_is_regex: bool,
+    /// The filter compiled, while it's a valid regex
+    filter_regex: Option<Regex>,
+    /// Metadata rows shown in the info overlay while it is open
+    info: Option<Vec<(&'static str, String)>>,
+    show_preview: bool,
+    /// When set, the preview stays on this path instead of following the
+    /// highlight
+    preview_pinned: Option<PathBuf>,
+    /// Last loaded preview, keyed by path, so we don't re-read every frame
+    preview_cache: Option<(PathBuf, preview::Content)>,
+    /// Lines scrolled past in the preview of this path; dropped once the
+    /// preview shows something else
+    preview_scroll: Option<(PathBuf, usize)>,
+    /// Whether `Enter` edits or views files
+    opener: Opener,
+    /// Temporarily reveal entries matching `config.hide_patterns`
+    show_pattern_hidden: bool,
+    /// Flips `show_pattern_hidden` until the next directory change
+    peek_hidden: bool,
+    /// Incremental search: unlike `filter`, matches are highlighted and
+    /// jumped to but nothing is hidden
+    search_query: TextInput,
+    /// Whether keystrokes currently go into `search_query`
+    searching: bool,
+    show_index_column: bool,
+    /// Inode number and hard link count column
+    show_inode_column: bool,
+    time_column: TimeColumn,
+    /// The active sort mode runs backwards
+    sort_reversed: bool,
+    /// Canonicalized `--root`; navigation never leaves it
+    root: Option<PathBuf>,
+    /// Directory pinned with `b`, jumped back to with `B`
+    project_root: Option<PathBuf>,
+    /// Where we were before the last directory change, and what was
+    /// highlighted there; `-` swaps back
+    prev_dir: Option<(PathBuf, Option<PathBuf>)>,
+    /// What was highlighted in each directory we've left, and how far the
+    /// listing was scrolled, so coming back looks the same
+    dir_views: HashMap<PathBuf, (PathBuf, usize)>,
+    /// `--pick-dirs`: Enter marks directories instead of entering them
+    pick_dirs: bool,
+    /// Archive being browsed; `cwd` is then a path inside it
+    archive: Option<archive::Archive>,
+    /// `--single`: Enter on a file picks it and ends the session
+    single: bool,
+    /// Destructive actions run without asking first; `--force` or `!`
+    force: bool,
+    /// File picked in `--single` mode; `run_app` exits once it's set
+    picked: Option<PathBuf>,
+    /// Recursive size being summed in the background, if any
+    dir_size: Option<dirsize::Job>,
+    /// The last finished size walk, for the footer while in that directory
+    last_dir_size: Option<(PathBuf, dirsize::Totals)>,
+    /// List every file under cwd (as relative paths) instead of just cwd
+    flatten: bool,
+    /// The flattened listing being walked in the background
+    flatten_job: Option<flatten::Job>,
+    /// The directory `all_entries` were flattened from, when they were
+    flat_root: Option<PathBuf>,
+    /// Append `ls -F` type indicators to names
+    show_type_suffix: bool,
+    /// Show each entry's full path rather than its name; also what the
+    /// export writes
+    show_full_paths: bool,
+    /// Position of each name in `sort_command`'s output
+    command_rank: HashMap<String, usize>,
+    /// Share of the width given to the list when the preview is shown, in percent
+    preview_split: u16,
+    /// Filesystem listing, with whichever subdirectory probes are on
+    fs_source: FsSource,
+    /// Re-read the listing this often, while on
+    auto_refresh: Option<Duration>,
+    last_refresh: Instant,
+    /// Recent-files overlay, while open
+    recent: Option<RecentView>,
+    /// Repository state per directory, looked up the first time the header
+    /// needs it; Ctrl-r forgets it all
+    git_states: HashMap<PathBuf, Option<git::RepoState>>,
+    /// Filesystem space around the directory it was read for; read again
+    /// whenever cwd changes
+    disk_space: Option<(PathBuf, Option<disk::Space>)>,
+    /// Content search in progress
+    grep_job: Option<grep::Job>,
+    /// Content search results, while open
+    grep: Option<GrepView>,
+    /// Template picker, while open
+    templates: Option<TemplateView>,
+    /// Places sidebar, while shown
+    places: Option<PlacesView>,
+    /// Paths emitted without exiting. Written straight to stdout when it is
+    /// piped; otherwise held here and printed once the UI is gone.
+    deferred_output: Vec<PathBuf>,
+    emitted: usize,
+    /// How many pickers this one is nested inside (Ctrl-o)
+    depth: usize,
+    /// What `launch` was asked to open, in place of opening it
+    #[cfg(test)]
+    launched: Vec<Vec<PathBuf>>,
+}
+
+/// How the session ended, for `main` to turn into output and an exit code.
+/// A script can tell the three apart by the exit status alone:
+///
+/// - 0: accepted (`Q`, or Enter on a file with `--single`); the paths are
+///   on stdout, one per line
+/// - `cancel_exit_code` (130 by default): cancelled (`q`, Esc or Ctrl-c);
+///   nothing more is printed, whatever was marked
+/// - 1: an error, reported on stderr
+enum Outcome {
+    /// Print these paths (possibly none) and exit 0
+    Accepted(Vec<PathBuf>),
+    /// Nothing picked; exit with `cancel_exit_code`
+    Cancelled,
+}
+
+struct RecentView {
+    items: Vec<PathBuf>,
+    state: ListState,
+}
+
+/// The places sidebar (Ctrl-b); the places themselves come from the config
+struct PlacesView {
+    state: ListState,
+    /// Keys go to the sidebar rather than the listing
+    focused: bool,
+}
+
+/// Templates to pick from for a new file (Ctrl-n)
+struct TemplateView {
+    items: Vec<PathBuf>,
+    state: ListState,
+}
+
+struct GrepView {
+    query: String,
+    hits: Vec<grep::Hit>,
+    state: ListState,
+}
+
+/// Bounds for `App::preview_split`, so neither pane gets squeezed away
+const PREVIEW_SPLIT_RANGE: (u16, u16) = (20, 80);
+
+/// Lines J/K scroll the preview by
+const PREVIEW_SCROLL_STEP: usize = 5;
+
+/// How a status message reads at a glance.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MessageKind {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl MessageKind {
+    fn style(self) -> Style {
+        match self {
+            MessageKind::Info => Style::default(),
+            MessageKind::Success => Style::default().fg(Color::Green),
+            MessageKind::Warning => Style::default().fg(Color::Yellow),
+            MessageKind::Error => Style::default().fg(Color::Red),
+        }
+    }
+}
+
+/// The message in the status line until the next key press.
+struct Status {
+    text: String,
+    kind: MessageKind,
+}
+
+impl Status {
+    fn new(kind: MessageKind, text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            kind,
+        }
+    }
+
+    fn info(text: impl Into<String>) -> Self {
+        Self::new(MessageKind::Info, text)
+    }
+
+    fn success(text: impl Into<String>) -> Self {
+        Self::new(MessageKind::Success, text)
+    }
+
+    fn warning(text: impl Into<String>) -> Self {
+        Self::new(MessageKind::Warning, text)
+    }
+
+    fn error(text: impl Into<String>) -> Self {
+        Self::new(MessageKind::Error, text)
+    }
+}
+
+/// A yes/no question and what to do on yes. Every confirmation goes
+/// through this: `App::ask` puts one up, `App::resolve_confirm` answers it
+/// and `render_confirm` draws it.
+struct Confirm {
+    message: String,
+    action: ConfirmAction,
+}
+
+enum ConfirmAction {
+    Open(OpenRequest),
+    Delete(Vec<PathBuf>),
+    /// Overwrite this file with the listing
+    Export(PathBuf),
+}
+
+impl ConfirmAction {
+    /// For the "... cancelled" status when the answer is no
+    fn name(&self) -> &'static str {
+        match self {
+            ConfirmAction::Open(_) => "open",
+            ConfirmAction::Delete(_) => "delete",
+            ConfirmAction::Export(_) => "export",
+        }
+    }
+
+    /// Destructive actions are prompted in red instead of yellow
+    fn is_destructive(&self) -> bool {
+        matches!(self, ConfirmAction::Delete(_) | ConfirmAction::Export(_))
+    }
+}
+
+/// How to reverse the last file operation, for `Ctrl-z`. Only one level is
+/// kept; each move, copy or duplicate replaces it.
+enum UndoOp {
+    /// Paths moved, as (from, to); undone by moving each back
+    Moved(Vec<(PathBuf, PathBuf)>),
+    /// Copies and duplicates made; undone by removing them
+    Created(Vec<PathBuf>),
+    /// Deletes are permanent, so this only says so
+    Deleted,
+}
+
+/// Files to launch together, with extra args for the program (e.g. `+42`).
+struct OpenRequest {
+    paths: Vec<PathBuf>,
+    args: Vec<String>,
+    opener: Opener,
+    /// Where to put the cursor; only honored when opening a single path
+    at: Option<Position>,
+}
+
+impl OpenRequest {
+    fn single(path: PathBuf, opener: Opener) -> Self {
+        Self {
+            paths: vec![path],
+            args: Vec::new(),
+            opener,
+            at: None,
+        }
+    }
+}
+
+/// A spot in a file, 1-based.
+#[derive(Clone, Copy)]
+struct Position {
+    line: usize,
+    column: Option<usize>,
+}
+
+impl Position {
+    /// `42` or `42:7`.
+    fn parse(text: &str) -> Option<Self> {
+        let (line, column) = match text.trim().split_once(':') {
+            Some((line, column)) => (line, Some(column.parse().ok()?)),
+            None => (text.trim(), None),
+        };
+        Some(Self {
+            line: line.parse().ok()?,
+            column,
+        })
+    }
+}
+
+/// A single-line text prompt and what to do with the text on Enter. Like
+/// `Confirm`, every such prompt goes through this: `App::ask_text` puts one
+/// up, `App::handle_prompt_key` edits and submits it and `render_input`
+/// draws it.
+struct Prompt {
+    label: &'static str,
+    input: TextInput,
+    action: PromptAction,
+}
+
+enum PromptAction {
+    /// Extra editor arguments (e.g. `+42` or `-R`) inserted before the path
+    EditorArgs(PathBuf),
+    /// A `line` or `line:column` to open the file at
+    OpenAt(PathBuf),
+    /// Text to look for in the listed files
+    Grep,
+    /// Days for marking by age: `7` or `-7` for newer, `+7` for older
+    MarkByAge,
+    /// Size for marking big files, e.g. `100M`
+    MarkLargerThan,
+    /// File to write the listing to, relative to cwd
+    Export,
+    /// Name for a new file copied from this template
+    NewFromTemplate(PathBuf),
+    /// Name for a copy of this entry, next to it
+    Duplicate(PathBuf),
+    /// Directory (or file, to highlight it) to go to
+    GoTo,
 }
 
 impl App {
-    fn new(start_dir: PathBuf) -> Result<Self> {
+    fn new(start_dir: PathBuf, config: Config, args: &Args) -> Result<Self> {
+        let density = config.density;
+        let auto_refresh_secs = config.auto_refresh_secs;
+        let probe_dir_access = config.probe_dir_access;
+        let warning = config.warnings.first().cloned().map(Status::warning);
+        let preview_split = config
+            .preview_split
+            .clamp(PREVIEW_SPLIT_RANGE.0, PREVIEW_SPLIT_RANGE.1);
         let mut app = Self {
+            config,
+            start_dir: start_dir.clone(),
             cwd: start_dir,
+            all_entries: Vec::new(),
             entries: Vec::new(),
             list_state: ListState::default(),
             selected_paths: HashSet::new(),
+            marked_sizes: HashMap::new(),
+            register: DEFAULT_REGISTER,
+            registers: HashMap::new(),
+            awaiting_register: false,
+            count: None,
+            show_ext_column: false,
+            status: warning,
+            group_marked: false,
+            sort_mode: SortMode::Name,
+            density,
+            confirm: None,
+            batch: None,
+            undo: None,
+            prompt: None,
+            filter: TextInput::default(),
+            filtering: false,
+            filter_case_sensitive: false,
+            filter_is_regex: false,
+            filter_regex: None,
+            info: None,
+            show_preview: false,
+            preview_pinned: None,
+            preview_scroll: None,
+            preview_cache: None,
+            opener: Opener::Editor,
+            show_pattern_hidden: false,
+            peek_hidden: false,
+            search_query: TextInput::default(),
+            searching: false,
+            show_index_column: false,
+            show_inode_column: false,
+            time_column: TimeColumn::Hidden,
+            sort_reversed: false,
+            root: args.root.clone(),
+            project_root: None,
+            prev_dir: None,
+            dir_views: HashMap::new(),
+            pick_dirs: args.pick_dirs,
+            archive: None,
+            single: args.single,
+            force: args.force,
+            picked: None,
+            dir_size: None,
+            last_dir_size: None,
+            flatten: false,
+            flatten_job: None,
+            flat_root: None,
+            show_type_suffix: false,
+            show_full_paths: false,
+            command_rank: HashMap::new(),
+            preview_split,
+            recent: None,
+            git_states: HashMap::new(),
+            disk_space: None,
+            grep_job: None,
+            grep: None,
+            templates: None,
+            places: None,
+            fs_source: FsSource {
+                probes: DirProbes {
+                    access: probe_dir_access,
+                    counts: false,
+                },
+            },
+            auto_refresh: (auto_refresh_secs > 0).then(|| Duration::from_secs(auto_refresh_secs)),
+            last_refresh: Instant::now(),
+            deferred_output: Vec::new(),
+            emitted: 0,
+            depth: 0,
+            #[cfg(test)]
+            launched: Vec::new(),
         };
         app.reload_entries()?;
         if !app.entries.is_empty() {
-            app.list_state.select(Some(0));
+            let i = args
+                .select
+                .as_deref()
+                .and_then(|name| app.entries.iter().position(|e| e.name == name))
+                .unwrap_or(0);
+            app.list_state.select(Some(i));
         }
         Ok(app)
     }
 
+    /// Where listings come from: the archive being browsed, if any,
+    /// otherwise the filesystem.
+    fn source(&self) -> &dyn EntrySource {
+        match &self.archive {
+            Some(a) => a,
+            None => &self.fs_source,
+        }
+    }
+
     fn reload_entries(&mut self) -> Result<()> {
-        self.entries = read_dir_sorted(&self.cwd)?;
+        self.preview_cache = None;
+        if let Some(job) = self.flatten_job.take() {
+            job.cancel();
+        }
+        if self.flatten {
+            self.start_flatten();
+            return Ok(());
+        }
+        self.flat_root = None;
+        match self.source().read_dir(&self.cwd) {
+            Ok(v) => self.all_entries = v,
+            // cwd was removed out from under us: climb to the nearest
+            // ancestor that still exists instead of bailing out
+            Err(err) if self.archive.is_none() && !self.cwd.is_dir() => {
+                let Some(ancestor) = self
+                    .cwd
+                    .ancestors()
+                    .skip(1)
+                    .find(|p| p.is_dir() && self.within_root(p))
+                else {
+                    return Err(err);
+                };
+                let ancestor = ancestor.to_path_buf();
+                self.status = Some(Status::warning(format!(
+                    "{} no longer exists, moved up to {}",
+                    self.cwd.display(),
+                    ancestor.display()
+                )));
+                self.cwd = ancestor;
+                self.all_entries = self.source().read_dir(&self.cwd)?;
+                self.list_state.select(Some(0));
+            }
+            Err(err) => return Err(err),
+        }
+        if !self.shows_pattern_hidden() {
+            let patterns = &self.config.hide_patterns;
+            self.all_entries
+                .retain(|e| !patterns.iter().any(|p| glob_match(p, &e.name)));
+        }
+        self.rank_by_command();
+        self.rebuild_view();
+        Ok(())
+    }
+
+    /// `reload_entries` for a key press. A directory that has become
+    /// unreadable leaves the old listing up, with the error in the status
+    /// line, rather than ending the session.
+    fn reload_or_report(&mut self) {
+        if let Err(err) = self.reload_entries() {
+            self.status = Some(Status::error(format!("{err:#}")));
+        }
+    }
+
+    /// Clean-slate refresh after big external changes: re-read the config,
+    /// drop marks on paths that are gone, stop any size walk and re-read
+    /// the listing (which also clears the preview and re-runs
+    /// `sort_command`).
+    fn refresh_all(&mut self) -> Result<()> {
+        self.config = Config::load();
+        // The places may have changed under the sidebar
+        let places = self.config.places.len();
+        if places == 0 {
+            self.places = None;
+        } else if let Some(view) = &mut self.places
+            && view.state.selected().is_some_and(|sel| sel >= places)
+        {
+            view.state.select(Some(places - 1));
+        }
+        self.git_states.clear();
+        self.disk_space = None;
+        self.last_dir_size = None;
+        self.fs_source.probes.access = self.config.probe_dir_access;
+        if let Some(job) = self.dir_size.take() {
+            job.cancel();
+        }
+        let stale: Vec<PathBuf> = self
+            .selected_paths
+            .iter()
+            .filter(|p| p.symlink_metadata().is_err())
+            .cloned()
+            .collect();
+        for path in &stale {
+            self.selected_paths.remove(path);
+            self.marked_sizes.remove(path);
+        }
+        self.reload_entries()?;
+        let mut msg = format!("refreshed {} entries", self.all_entries.len());
+        if !stale.is_empty() {
+            msg.push_str(&format!(", dropped {} stale marks", stale.len()));
+        }
+        self.status = Some(match self.config.warnings.first() {
+            Some(warning) => Status::warning(format!("{msg}; {warning}")),
+            None => Status::info(msg),
+        });
+        Ok(())
+    }
+
+    fn toggle_flatten(&mut self) {
+        self.flatten = !self.flatten;
+        self.list_state.select(Some(0));
+        self.reload_or_report();
+    }
+
+    /// Walk cwd for the flattened listing: in the background on disk,
+    /// where it can take a while, or right away inside an archive. A
+    /// re-walk of the directory already shown keeps the old listing up
+    /// until the new one is in; otherwise the listing starts out empty.
+    fn start_flatten(&mut self) {
+        let patterns = self.config.hide_patterns.clone();
+        let show_hidden = self.shows_pattern_hidden();
+        let skip = move |name: &str| !show_hidden && patterns.iter().any(|p| glob_match(p, name));
+        if self.archive.is_some() {
+            let files = flatten::walk(
+                self.source(),
+                &self.cwd,
+                &skip,
+                &AtomicBool::new(false),
+                &mut |_| {},
+            );
+            self.show_flattened(self.cwd.clone(), files);
+            return;
+        }
+        if self.flat_root.as_ref() != Some(&self.cwd) {
+            self.flat_root = None;
+            self.all_entries.clear();
+            self.rebuild_view();
+        }
+        self.flatten_job = Some(flatten::Job::spawn(
+            self.fs_source,
+            self.cwd.clone(),
+            Box::new(skip),
+        ));
+        self.poll_flatten();
+    }
+
+    /// Show walk progress while cwd is first being flattened, and the
+    /// listing once it's in; called every tick.
+    fn poll_flatten(&mut self) {
+        let Some(job) = &mut self.flatten_job else {
+            return;
+        };
+        job.poll();
+        let first = self.flat_root.is_none();
+        if !job.done {
+            if first {
+                self.status = Some(Status::info(format!(
+                    "flattening {}: {} files so far (Esc cancels)",
+                    job.root.display(),
+                    job.found
+                )));
+            }
+            return;
+        }
+        let job = self.flatten_job.take().expect("checked above");
+        let count = job.files.len();
+        let root = job.root.clone();
+        self.show_flattened(job.root, job.files);
+        if first {
+            self.status = Some(Status::info(format!(
+                "{count} files under {} (depth ≤ {})",
+                root.display(),
+                flatten::MAX_DEPTH
+            )));
+        }
+    }
+
+    fn show_flattened(&mut self, root: PathBuf, files: Vec<Entry>) {
+        let highlighted = self.selected_entry().map(|e| e.path.clone());
+        self.all_entries = files;
+        self.flat_root = Some(root);
+        self.rank_by_command();
+        self.rebuild_view();
+        if let Some(path) = highlighted {
+            self.select_path(&path);
+        }
+    }
+
+    /// Stop the walk. A first walk of cwd goes back to the plain listing;
+    /// a re-walk leaves the listing from the last one.
+    fn cancel_flatten(&mut self) -> Result<()> {
+        let Some(job) = self.flatten_job.take() else {
+            return Ok(());
+        };
+        job.cancel();
+        if self.flat_root.is_none() {
+            self.flatten = false;
+            self.reload_entries()?;
+        }
+        self.status = Some(Status::info("flattening cancelled"));
         Ok(())
     }
 
+    fn toggle_pattern_hidden(&mut self) {
+        self.show_pattern_hidden = !self.show_pattern_hidden;
+        self.status = Some(Status::info(if self.show_pattern_hidden {
+            "showing entries matching hide_patterns".to_string()
+        } else {
+            "hiding entries matching hide_patterns".to_string()
+        }));
+        self.reload_or_report();
+    }
+
+    /// Whether `hide_patterns` entries are listed right now, counting a peek.
+    fn shows_pattern_hidden(&self) -> bool {
+        self.show_pattern_hidden != self.peek_hidden
+    }
+
+    /// Flip `hide_patterns` visibility for this directory only; leaving it
+    /// puts things back as `I` left them.
+    fn toggle_peek_hidden(&mut self) {
+        self.peek_hidden = !self.peek_hidden;
+        self.status = Some(Status::info(
+            match (self.peek_hidden, self.shows_pattern_hidden()) {
+                (false, _) => "peek over".to_string(),
+                (true, true) => "peeking at hidden entries until you leave".to_string(),
+                (true, false) => "hiding hidden entries until you leave".to_string(),
+            },
+        ));
+        self.reload_or_report();
+    }
+
+    fn clamp_selection(&mut self) {
+        let len = self.entries.len();
+        match self.list_state.selected() {
+            _ if len == 0 => self.list_state.select(None),
+            Some(i) if i >= len => self.list_state.select(Some(len - 1)),
+            None => self.list_state.select(Some(0)),
+            _ => {}
+        }
+    }
+
+    /// Rebuild `entries` from `all_entries`: apply the filter, then sort by
+    /// `sort_mode`, floating marked entries to the top when `group_marked` is on. The
+    /// highlight stays on the same entry if it is still visible.
+    fn rebuild_view(&mut self) {
+        let current = self.selected_entry().map(|e| e.path.clone());
+        let query = self.filter.value();
+        let lower = query.to_lowercase();
+        let case_sensitive = self.filter_case_sensitive;
+        self.filter_regex = None;
+        if self.filter_is_regex && !query.is_empty() {
+            // A pattern that doesn't parse, often because it's half typed,
+            // leaves the listing unfiltered; the filter line says so
+            self.filter_regex = RegexBuilder::new(query)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .ok();
+        }
+        let regex = self.filter_is_regex.then_some(&self.filter_regex);
+        self.entries = self
+            .all_entries
+            .iter()
+            .filter(|e| match regex {
+                Some(re) => re.as_ref().is_none_or(|re| re.is_match(&e.name)),
+                None if case_sensitive => e.name.contains(query),
+                None => e.name.to_lowercase().contains(&lower),
+            })
+            .cloned()
+            .collect();
+        let mode = self.sort_mode;
+        let marked = &self.selected_paths;
+        let group_marked = self.group_marked;
+        let reversed = self.sort_reversed;
+        let rank = &self.command_rank;
+        // Names the command dropped sort after the ones it printed
+        let rank_of = |e: &Entry| rank.get(&e.name).copied().unwrap_or(usize::MAX);
+        self.entries.sort_by(|a, b| {
+            // Marked-ness is the primary key when grouping, so each group
+            // still follows the active sort mode
+            let by_mark = if group_marked {
+                marked.contains(&b.path).cmp(&marked.contains(&a.path))
+            } else {
+                Ordering::Equal
+            };
+            // Directories stay first when reversed; only the order within
+            // each group flips
+            let by_dir = if mode.groups_dirs() {
+                b.is_dir().cmp(&a.is_dir())
+            } else {
+                Ordering::Equal
+            };
+            by_mark.then(by_dir).then_with(|| {
+                let ord = match mode {
+                    SortMode::Command if !rank.is_empty() => rank_of(a).cmp(&rank_of(b)),
+                    _ => compare_entries(a, b, mode),
+                };
+                if reversed { ord.reverse() } else { ord }
+            })
+        });
+        if let Some(p) = current {
+            self.select_path(&p);
+        }
+        self.clamp_selection();
+    }
+
+    fn select_path(&mut self, path: &Path) {
+        if let Some(i) = self.entries.iter().position(|e| e.path == path) {
+            self.list_state.select(Some(i));
+        }
+    }
+
+    /// Flip the direction of the active sort mode; cycling modes resets it.
+    fn reverse_sort(&mut self) {
+        self.sort_reversed = !self.sort_reversed;
+        self.rebuild_view();
+    }
+
+    fn cycle_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+        self.sort_reversed = false;
+        if self.sort_mode == SortMode::Command && self.config.sort_command.is_none() {
+            self.sort_mode = self.sort_mode.next();
+        }
+        self.rank_by_command();
+        self.rebuild_view();
+    }
+
+    /// Run `all_entries` through `sort_command` when that sort is active,
+    /// leaving `command_rank` empty (so the built-in order applies) if the
+    /// command fails.
+    fn rank_by_command(&mut self) {
+        self.command_rank.clear();
+        let Some(cmd) = &self.config.sort_command else {
+            return;
+        };
+        if self.sort_mode != SortMode::Command {
+            return;
+        }
+        let names: Vec<&str> = self.all_entries.iter().map(|e| e.name.as_str()).collect();
+        match external_order(cmd, &names) {
+            Ok(order) => {
+                self.command_rank = order
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, name)| (name, i))
+                    .collect();
+            }
+            Err(e) => {
+                self.status = Some(Status::warning(format!(
+                    "sort_command failed, using name order: {e:#}"
+                )));
+            }
+        }
+    }
+
+    fn toggle_group_marked(&mut self) {
+        self.group_marked = !self.group_marked;
+        self.rebuild_view();
+    }
+
     fn selected_index(&self) -> Option<usize> {
         self.list_state.selected()
     }
 
-    fn selected_entry(&mut self) -> Option<&Entry> {
+    fn selected_entry(&self) -> Option<&Entry> {
         self.selected_index().and_then(|i| self.entries.get(i))
     }
 
@@ -82,216 +1078,3610 @@ impl App {
         self.move_by(-1);
     }
 
+    /// Move to the next (`step` 1) or previous (`step` -1) directory,
+    /// skipping files and wrapping around. No-op without directories.
+    fn move_to_dir(&mut self, step: isize) {
+        let len = self.entries.len() as isize;
+        let start = self.selected_index().unwrap_or(0) as isize;
+        let next = (1..=len)
+            .map(|i| (start + i * step).rem_euclid(len) as usize)
+            .find(|&i| self.entries[i].is_dir());
+        if let Some(i) = next {
+            self.list_state.select(Some(i));
+        }
+    }
+
     fn enter(&mut self) -> Result<()> {
         if let Some(e) = self.selected_entry() {
-            if e.is_dir {
+            if e.is_dir() && self.pick_dirs {
+                self.toggle_mark();
+            } else if e.locked {
+                self.status = Some(Status::error(format!("permission denied: {}", e.name)));
+            } else if e.is_dir() {
                 // end borrow before mutating self
                 let path = e.path.clone();
-                self.cwd = path;
-                self.reload_entries()?;
-                self.list_state.select(Some(0));
+                self.change_dir(path)?;
+            } else if self.single {
+                self.picked = Some(e.path.clone());
+            } else if archive::is_archive(&e.path) && self.archive.is_none() {
+                let path = e.path.clone();
+                self.enter_archive(path)?;
             } else {
-                open_with_editor(&e.path)?;
+                let path = e.path.clone();
+                self.open_file(OpenRequest::single(path, self.opener))?;
             }
         }
         Ok(())
     }
 
-    fn toggle_mark(&mut self) {
-        if let Some(e) = self.selected_entry() {
-            let p = e.path.clone();
-            if !self.selected_paths.insert(p.clone()) {
-                self.selected_paths.remove(&p);
+    /// Browse inside the archive at `path` as if it were a directory.
+    fn enter_archive(&mut self, path: PathBuf) -> Result<()> {
+        match archive::Archive::open(&path) {
+            Ok(a) => {
+                // change_dir lists through the archive, so it goes in first
+                // and comes back out if change_dir refuses
+                let prev = self.archive.replace(a);
+                self.change_dir(path.clone())?;
+                if self.cwd != path {
+                    self.archive = prev;
+                }
             }
+            Err(e) => self.status = Some(Status::error(format!("{e:#}"))),
         }
+        Ok(())
     }
 
-    fn up_dir(&mut self) -> Result<()> {
-        if let Some(parent) = self.cwd.parent() {
-            self.cwd = parent.to_path_buf();
-            self.reload_entries()?;
-            self.list_state.select(Some(0));
+    /// Navigate into the highlighted directory, whatever Enter is bound to.
+    fn enter_dir(&mut self) -> Result<()> {
+        if let Some(e) = self.selected_entry()
+            && e.is_dir()
+        {
+            if e.locked {
+                self.status = Some(Status::error(format!("permission denied: {}", e.name)));
+                return Ok(());
+            }
+            let path = e.path.clone();
+            self.change_dir(path)?;
         }
         Ok(())
     }
-}
 
-fn main() -> Result<()> {
-    let start_dir = env::current_dir()?;
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = ratatui::backend::CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-    terminal.clear()?;
+    /// Whether `dir` is inside `--root` (always true without one).
+    fn within_root(&self, dir: &Path) -> bool {
+        // Paths inside an archive don't exist on disk; the archive does
+        let dir = match &self.archive {
+            Some(a) if a.contains(dir) => &a.path,
+            _ => dir,
+        };
+        self.root
+            .as_ref()
+            .is_none_or(|root| fs::canonicalize(dir).is_ok_and(|d| d.starts_with(root)))
+    }
 
-    let res = run_app(&mut terminal, start_dir);
+    fn change_dir(&mut self, dir: PathBuf) -> Result<()> {
+        if !self.within_root(&dir) {
+            self.status = Some(Status::error(format!(
+                "{} is outside the root",
+                dir.display()
+            )));
+            return Ok(());
+        }
+        if self.archive.as_ref().is_some_and(|a| !a.contains(&dir)) {
+            self.archive = None;
+        }
+        let highlighted = self.selected_entry().map(|e| e.path.clone());
+        let offset = self.list_state.offset();
+        let prev = std::mem::replace(&mut self.cwd, dir);
+        let peeking = std::mem::take(&mut self.peek_hidden);
+        if let Err(err) = self.reload_entries() {
+            // Typically permission denied: stay where we were
+            self.cwd = prev;
+            self.peek_hidden = peeking;
+            self.status = Some(Status::error(format!("{err:#}")));
+            return Ok(());
+        }
+        if let Some(path) = &highlighted {
+            self.dir_views.insert(prev.clone(), (path.clone(), offset));
+        }
+        if prev != self.cwd {
+            self.prev_dir = Some((prev, highlighted));
+        }
+        self.filter.clear();
+        self.filtering = false;
+        self.list_state.select(Some(0));
+        *self.list_state.offset_mut() = 0;
+        self.rebuild_view();
+        // The offset may leave the highlight off screen if the listing has
+        // changed since; drawing scrolls back to it
+        if let Some((path, offset)) = self.dir_views.get(&self.cwd).cloned() {
+            self.select_path(&path);
+            *self.list_state.offset_mut() = offset;
+        }
+        Ok(())
+    }
 
-    // Restore
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    /// A paste goes into whichever text input is open; otherwise it's taken
+    /// as a path to jump to: a directory is entered, a file is highlighted
+    /// in its directory.
+    fn handle_paste(&mut self, text: &str) -> Result<()> {
+        if let Some(prompt) = &mut self.prompt {
+            prompt.input.insert_str(text);
+        } else if self.filtering {
+            self.filter.insert_str(text);
+            self.rebuild_view();
+        } else if self.searching {
+            self.search_query.insert_str(text);
+            self.jump_to_match(0, 1);
+        } else if self.confirm.is_none() && self.batch.is_none() {
+            self.jump_to_path(text)?;
+        }
+        Ok(())
+    }
 
-    if let Err(e) = res {
-        eprintln!("error: {e:?}");
-        std::process::exit(1);
+    fn jump_to_path(&mut self, text: &str) -> Result<()> {
+        // File managers paste `file://` URLs, shells paste quoted paths
+        let text = text.trim();
+        let text = text.strip_prefix("file://").unwrap_or(text);
+        let text = text
+            .strip_prefix('\'')
+            .and_then(|t| t.strip_suffix('\''))
+            .unwrap_or(text);
+        if text.is_empty() {
+            return Ok(());
+        }
+        let path = normalize_path(&self.cwd, &config::expand_home(text));
+        if path.is_dir() {
+            self.change_dir(path)
+        } else if path.exists()
+            && let Some(parent) = path.parent()
+        {
+            self.change_dir(parent.to_path_buf())?;
+            self.select_path(&path);
+            Ok(())
+        } else {
+            // Name the first piece that's missing rather than the whole path
+            let found = path.ancestors().find(|a| a.exists()).unwrap_or(&path);
+            let missing = path
+                .strip_prefix(found)
+                .ok()
+                .and_then(|rest| rest.components().next())
+                .map_or_else(String::new, |c| {
+                    c.as_os_str().to_string_lossy().into_owned()
+                });
+            self.status = Some(Status::error(format!(
+                "{text}: no {missing} in {}",
+                found.display()
+            )));
+            Ok(())
+        }
     }
-    Ok(())
-}
 
-fn run_app(
-    terminal: &mut Terminal<ratatui::backend::CrosstermBackend<io::Stdout>>,
-    start_dir: PathBuf,
-) -> Result<()> {
-    let mut app = App::new(start_dir)?;
-    loop {
-        terminal.draw(|f| ui(f, &mut app))?;
+    /// Go to the directory containing the highlighted file and keep the file
+    /// highlighted there, leaving the flattened view. Directories are simply
+    /// entered.
+    fn reveal(&mut self) -> Result<()> {
+        let Some(e) = self.selected_entry() else {
+            return Ok(());
+        };
+        // Not `enter`, which marks directories under --pick-dirs
+        if e.is_dir() {
+            return self.enter_dir();
+        }
+        let path = e.path.clone();
+        if let Some(parent) = path.parent() {
+            // The file is listed plainly in its own directory
+            self.flatten = false;
+            self.change_dir(parent.to_path_buf())?;
+            self.select_path(&path);
+        }
+        Ok(())
+    }
 
-        // Use poll so we can redraw at intervals if needed (smooth resize, etc.)
-        if event::poll(Duration::from_millis(250))? {
-            if let Event::Key(k) = event::read()? {
-                // Ignore repeat events on key hold for some terminals
-                if k.kind == KeyEventKind::Release {
-                    continue;
-                }
-                match k.code {
-                    KeyCode::Char('q') | KeyCode::Esc => break,
-                    KeyCode::Down | KeyCode::Char('j') => app.next(),
-                    KeyCode::Up | KeyCode::Char('k') => app.prev(),
-                    KeyCode::Backspace => app.up_dir()?,
-                    KeyCode::Char('r') => app.reload_entries()?,
-                    KeyCode::Char(' ') => app.toggle_mark(),
-                    KeyCode::Enter => app.enter()?,
-                    _ => {}
-                }
+    fn open_file(&mut self, req: OpenRequest) -> Result<()> {
+        if let Some(a) = &self.archive
+            && req.paths.iter().any(|p| a.contains(p))
+        {
+            self.status = Some(Status::warning(
+                "archives are browsed read-only; extract to open files",
+            ));
+            return Ok(());
+        }
+        // Opening a FIFO would block until something writes to it, and
+        // devices and sockets aren't content to edit
+        for path in &req.paths {
+            if let Ok(md) = fs::metadata(path)
+                && Kind::of(md.file_type()).is_special()
+            {
+                self.status = Some(Status::warning(format!(
+                    "not opening {}: it's a {}",
+                    file_name_of(path),
+                    Kind::of(md.file_type()).label()
+                )));
+                return Ok(());
             }
         }
+        if self.config.confirm_network_open && req.paths.iter().any(|p| netfs::is_network_path(p)) {
+            let what = match req.paths.as_slice() {
+                [path] => file_name_of(path),
+                paths => format!("some of the {} files", paths.len()),
+            };
+            return self.ask(
+                format!("{what} on a network filesystem. Open anyway?"),
+                ConfirmAction::Open(req),
+            );
+        }
+        self.launch(&req)
     }
-    Ok(())
-}
 
-fn ui(f: &mut Frame, app: &mut App) {
-    let size = f.size();
+    fn launch(&mut self, req: &OpenRequest) -> Result<()> {
+        // Tests mustn't start an editor or touch the recent list
+        #[cfg(test)]
+        self.launched.push(req.paths.clone());
+        #[cfg(not(test))]
+        self.run_opener(req);
+        Ok(())
+    }
+
+    #[cfg_attr(test, allow(dead_code))]
+    fn run_opener(&mut self, req: &OpenRequest) {
+        for path in &req.paths {
+            recent::record(path);
+        }
+        // Whatever was edited may have changed what git reports
+        self.git_states.clear();
+        // The fallbacks are editors and pagers, no use for a diff
+        let fallbacks = match req.opener {
+            Opener::Diff => &[],
+            _ => self.config.fallback_openers.as_slice(),
+        };
+        // open_with has put the TUI back by the time it fails, so failing to
+        // run anything is just news for the status line
+        if let Err(err) = open_with(req.opener, &req.paths, &req.args, req.at, fallbacks) {
+            self.status = Some(Status::error(format!("{err:#}")));
+        }
+    }
+
+    /// Open all marked files in one editor invocation, or the highlighted
+    /// file if nothing is marked.
+    fn open_marked(&mut self) -> Result<()> {
+        let mut paths: Vec<PathBuf> = self
+            .selected_paths
+            .iter()
+            .filter(|p| !p.is_dir())
+            .cloned()
+            .collect();
+        paths.sort();
+        if paths.is_empty()
+            && let Some(e) = self.selected_entry()
+            && !e.is_dir()
+        {
+            paths.push(e.path.clone());
+        }
+        if paths.is_empty() {
+            self.status = Some(Status::warning("no files to open"));
+            return Ok(());
+        }
+        self.open_file(OpenRequest {
+            paths,
+            args: Vec::new(),
+            opener: Opener::Editor,
+            at: None,
+        })
+    }
+
+    /// Compare the two marked files with `$DIFFTOOL`, or `diff -u`.
+    fn diff_marked(&mut self) -> Result<()> {
+        let mut paths: Vec<PathBuf> = self.selected_paths.iter().cloned().collect();
+        if paths.len() != 2 {
+            self.status = Some(Status::error(format!(
+                "mark exactly two files to compare ({} marked)",
+                paths.len()
+            )));
+            return Ok(());
+        }
+        paths.sort();
+        if let Some(dir) = paths.iter().find(|p| p.is_dir()) {
+            self.status = Some(Status::error(format!(
+                "cannot diff a directory: {}",
+                file_name_of(dir)
+            )));
+            return Ok(());
+        }
+        self.open_file(OpenRequest {
+            paths,
+            args: Vec::new(),
+            opener: Opener::Diff,
+            at: None,
+        })
+    }
+
+    /// Hand the highlighted entry to the desktop's default application,
+    /// leaving the picker running.
+    fn open_with_system(&mut self) {
+        let Some(e) = self.selected_entry() else {
+            return;
+        };
+        if self.archive.is_some() {
+            self.status = Some(Status::warning(
+                "archives are browsed read-only; extract to open files",
+            ));
+            return;
+        }
+        let name = e.name.clone();
+        self.status = Some(match open_detached(&e.path) {
+            Ok(program) => Status::success(format!("opened {name} with {program}")),
+            Err(err) => Status::error(format!("couldn't open {name}: {err}")),
+        });
+    }
+
+    /// Open the highlighted file read-only in the pager, whatever `Enter`
+    /// is currently set to.
+    fn view(&mut self) -> Result<()> {
+        if let Some(e) = self.selected_entry()
+            && !e.is_dir()
+        {
+            let path = e.path.clone();
+            self.open_file(OpenRequest::single(path, Opener::Pager))?;
+        }
+        Ok(())
+    }
+
+    fn toggle_opener(&mut self) {
+        self.opener = match self.opener {
+            Opener::Editor => Opener::Pager,
+            Opener::Pager | Opener::Diff => Opener::Editor,
+        };
+        self.status = Some(Status::info(format!(
+            "Enter now opens files with ${}",
+            self.opener.env_var()
+        )));
+    }
+
+    fn start_filter(&mut self) {
+        self.filtering = true;
+        self.filter.clear();
+        self.rebuild_view();
+    }
+
+    fn clear_filter(&mut self) {
+        self.filtering = false;
+        self.filter.clear();
+        self.rebuild_view();
+    }
+
+    fn start_search(&mut self) {
+        self.searching = true;
+        self.search_query.clear();
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) {
+        if self.search_query.handle(key) {
+            // Incremental: stay put if the highlighted entry still matches
+            self.jump_to_match(0, 1);
+            return;
+        }
+        match key.code {
+            KeyCode::Esc => {
+                self.searching = false;
+                self.search_query.clear();
+            }
+            KeyCode::Enter => self.searching = false,
+            _ => {}
+        }
+    }
+
+    /// Move to the next entry matching `search_query`, looking `step`
+    /// entries at a time from `skip` past the current one, wrapping around.
+    fn jump_to_match(&mut self, skip: usize, step: isize) {
+        let len = self.entries.len();
+        if len == 0 || self.search_query.is_empty() {
+            return;
+        }
+        let start = self.selected_index().unwrap_or(0) as isize;
+        let found = (0..len as isize)
+            .map(|i| (start + (skip as isize + i) * step).rem_euclid(len as isize) as usize)
+            .find(|&i| {
+                find_ignore_case(&self.entries[i].name, self.search_query.value()).is_some()
+            });
+        match found {
+            Some(i) => self.list_state.select(Some(i)),
+            None => {
+                self.status = Some(Status::warning(format!(
+                    "no match for \"{}\"",
+                    self.search_query.value()
+                )))
+            }
+        }
+    }
+
+    fn handle_filter_key(&mut self, key: KeyEvent) -> Result<()> {
+        if self.filter.handle(key) {
+            self.rebuild_view();
+            return Ok(());
+        }
+        let code = key.code;
+        match code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::ALT) => {
+                self.filter_case_sensitive = !self.filter_case_sensitive;
+                self.rebuild_view();
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::ALT) => {
+                self.filter_is_regex = !self.filter_is_regex;
+                self.rebuild_view();
+            }
+            KeyCode::Esc => self.clear_filter(),
+            KeyCode::Down => self.next(),
+            KeyCode::Up => self.prev(),
+            // Tab only ever auto-enters; Enter falls back to keeping the
+            // filter and returning to normal navigation
+            KeyCode::Tab | KeyCode::Enter => {
+                if self.enter_unique_dir_match()? {
+                    // change_dir already left filter mode
+                } else if code == KeyCode::Enter {
+                    self.filtering = false;
+                } else {
+                    self.status = Some(Status::warning("no unique directory match"));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Enter the only directory matching the filter, if there is exactly one.
+    /// Files never auto-open this way.
+    fn enter_unique_dir_match(&mut self) -> Result<bool> {
+        let mut dirs = self.entries.iter().filter(|e| e.is_dir());
+        let (Some(dir), None) = (dirs.next(), dirs.next()) else {
+            return Ok(false);
+        };
+        let path = dir.path.clone();
+        self.change_dir(path)?;
+        Ok(true)
+    }
+
+    /// Print the highlighted path for a consuming process and keep going.
+    fn emit_selected(&mut self) -> Result<()> {
+        let Some(e) = self.selected_entry() else {
+            return Ok(());
+        };
+        let path = e.path.clone();
+        if term::stdout_is_terminal() {
+            // Printing now would scribble over the UI
+            self.deferred_output.push(path);
+        } else {
+            let mut out = io::stdout();
+            writeln!(out, "{}", path.display())?;
+            out.flush()?;
+        }
+        self.emitted += 1;
+        Ok(())
+    }
+
+    /// Wrap up the session. `Q` accepts the marks (and anything emitted),
+    /// even if there are none; `q`/Esc cancel, dropping them.
+    fn finish(mut self, accept: bool) -> Outcome {
+        if !accept {
+            return Outcome::Cancelled;
+        }
+        let mut marked: Vec<PathBuf> = self.selected_paths.into_iter().collect();
+        marked.sort();
+        self.deferred_output.extend(marked);
+        Outcome::Accepted(self.deferred_output)
+    }
+
+    /// A fresh picker on the highlighted directory, for `run_app` to run as
+    /// a sub-session. It starts from the same config and flags but keeps
+    /// its own marks, registers and view settings.
+    fn nested(&mut self, args: &Args) -> Result<Option<App>> {
+        let Some(e) = self.selected_entry().filter(|e| e.is_dir()) else {
+            self.status = Some(Status::warning(
+                "highlight a directory to open a nested picker",
+            ));
+            return Ok(None);
+        };
+        if self.archive.is_some() {
+            self.status = Some(Status::warning("nested pickers don't open inside archives"));
+            return Ok(None);
+        }
+        if e.locked {
+            self.status = Some(Status::error(format!("permission denied: {}", e.name)));
+            return Ok(None);
+        }
+        // Its warnings were already shown when this session started
+        let config = Config {
+            warnings: Vec::new(),
+            ..self.config.clone()
+        };
+        let mut app = App::new(e.path.clone(), config, args)?;
+        app.depth = self.depth + 1;
+        Ok(Some(app))
+    }
+
+    /// Take what a nested picker ended with: its picks become marks here,
+    /// except in `--single` mode, where a pick ends this session too.
+    fn adopt_nested(&mut self, outcome: Outcome) {
+        let Outcome::Accepted(paths) = outcome else {
+            return;
+        };
+        if self.single
+            && let Some(path) = paths.first()
+        {
+            self.picked = Some(path.clone());
+            return;
+        }
+        for path in &paths {
+            let size = match fs::symlink_metadata(path) {
+                Ok(md) if !md.is_dir() => md.len(),
+                _ => 0,
+            };
+            self.selected_paths.insert(path.clone());
+            self.marked_sizes.insert(path.clone(), size);
+        }
+        if !paths.is_empty() {
+            self.status = Some(Status::success(format!(
+                "marked {} from the nested picker",
+                paths.len()
+            )));
+        }
+        if self.group_marked {
+            self.rebuild_view();
+        }
+    }
+
+    fn show_recent(&mut self) {
+        let mut items = recent::load();
+        items.retain(|p| p.parent().is_some_and(|dir| self.within_root(dir)));
+        if items.is_empty() {
+            self.status = Some(Status::info("no recent files"));
+            return;
+        }
+        self.recent = Some(RecentView {
+            items,
+            state: ListState::default().with_selected(Some(0)),
+        });
+    }
+
+    fn handle_recent_key(&mut self, code: KeyCode) -> Result<()> {
+        let Some(view) = self.recent.as_mut() else {
+            return Ok(());
+        };
+        let len = view.items.len();
+        let sel = view.state.selected().unwrap_or(0);
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => view.state.select(Some((sel + 1) % len)),
+            KeyCode::Up | KeyCode::Char('k') => view.state.select(Some((sel + len - 1) % len)),
+            KeyCode::Esc | KeyCode::Char('R') => self.recent = None,
+            // Enter opens the file, `o` goes to it in the listing
+            KeyCode::Enter => {
+                let path = view.items[sel].clone();
+                self.recent = None;
+                self.open_file(OpenRequest::single(path, self.opener))?;
+            }
+            KeyCode::Char('o') => {
+                let path = view.items[sel].clone();
+                self.recent = None;
+                if let Some(parent) = path.parent() {
+                    self.change_dir(parent.to_path_buf())?;
+                    self.select_path(&path);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Search the contents of the listed files (everything under cwd when
+    /// flattened) in the background; `poll_grep` shows the ones that match.
+    fn grep(&mut self, query: &str) {
+        if query.is_empty() {
+            return;
+        }
+        if self.archive.is_some() {
+            self.status = Some(Status::warning("can't search inside archives"));
+            return;
+        }
+        let paths: Vec<PathBuf> = self
+            .entries
+            .iter()
+            .filter(|e| e.kind == Kind::File)
+            .map(|e| e.path.clone())
+            .collect();
+        if let Some(job) = &self.grep_job {
+            job.cancel();
+        }
+        self.grep_job = Some(grep::Job::spawn(paths, query));
+        self.poll_grep();
+    }
+
+    /// Show search progress in the status line, and the results once it's
+    /// done; called every tick.
+    fn poll_grep(&mut self) {
+        let Some(job) = &mut self.grep_job else {
+            return;
+        };
+        job.poll();
+        if !job.done {
+            self.status = Some(Status::info(format!(
+                "searching for \"{}\": {} of {} files, {} matching (Esc cancels)",
+                job.query,
+                job.searched,
+                job.total,
+                job.hits.len()
+            )));
+            return;
+        }
+        let job = self.grep_job.take().expect("checked above");
+        self.status = None;
+        self.show_grep_hits(job);
+    }
+
+    /// Stop the search, keeping what it found so far.
+    fn cancel_grep(&mut self) {
+        let Some(mut job) = self.grep_job.take() else {
+            return;
+        };
+        job.cancel();
+        job.poll();
+        let (searched, total) = (job.searched, job.total);
+        self.show_grep_hits(job);
+        self.status = Some(Status::warning(format!(
+            "search cancelled after {searched} of {total} files"
+        )));
+    }
+
+    fn show_grep_hits(&mut self, job: grep::Job) {
+        if job.hits.is_empty() {
+            self.status = Some(Status::info(format!(
+                "no listed file contains \"{}\"",
+                job.query
+            )));
+            return;
+        }
+        self.grep = Some(GrepView {
+            query: job.query,
+            hits: job.hits,
+            state: ListState::default().with_selected(Some(0)),
+        });
+    }
+
+    fn handle_grep_key(&mut self, code: KeyCode) -> Result<()> {
+        let Some(view) = self.grep.as_mut() else {
+            return Ok(());
+        };
+        let len = view.hits.len();
+        let sel = view.state.selected().unwrap_or(0);
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => view.state.select(Some((sel + 1) % len)),
+            KeyCode::Up | KeyCode::Char('k') => view.state.select(Some((sel + len - 1) % len)),
+            KeyCode::Esc | KeyCode::Char('G') => self.grep = None,
+            // Enter opens the file at its first match; `o` goes to it in
+            // the listing. The view stays open after opening so the next
+            // hit is a keypress away.
+            KeyCode::Enter => {
+                let hit = &view.hits[sel];
+                let req = OpenRequest {
+                    at: Some(Position {
+                        line: hit.line,
+                        column: Some(hit.column),
+                    }),
+                    ..OpenRequest::single(hit.path.clone(), self.opener)
+                };
+                self.open_file(req)?;
+            }
+            KeyCode::Char('o') => {
+                let path = view.hits[sel].path.clone();
+                self.grep = None;
+                self.select_path(&path);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Ctrl-b: show and focus the places sidebar, focus it again if it's
+    /// shown but unfocused, or hide it.
+    fn toggle_places(&mut self) {
+        match &mut self.places {
+            None if self.config.places.is_empty() => {
+                self.status = Some(Status::warning(
+                    "no places; add some with places in the config",
+                ));
+            }
+            None => {
+                self.places = Some(PlacesView {
+                    state: ListState::default().with_selected(Some(0)),
+                    focused: true,
+                })
+            }
+            Some(view) if !view.focused => view.focused = true,
+            Some(_) => self.places = None,
+        }
+    }
+
+    fn handle_places_key(&mut self, key: KeyEvent) -> Result<()> {
+        let Some(view) = self.places.as_mut() else {
+            return Ok(());
+        };
+        let len = self.config.places.len();
+        if len == 0 {
+            self.places = None;
+            return Ok(());
+        }
+        let sel = view.state.selected().unwrap_or(0).min(len - 1);
+        match key.code {
+            KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.places = None
+            }
+            KeyCode::Down | KeyCode::Char('j') => view.state.select(Some((sel + 1) % len)),
+            KeyCode::Up | KeyCode::Char('k') => view.state.select(Some((sel + len - 1) % len)),
+            // Back to the listing, leaving the sidebar up
+            KeyCode::Esc | KeyCode::Tab => view.focused = false,
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                view.focused = false;
+                let (name, path) = self.config.places[sel].clone();
+                if path.is_dir() {
+                    self.change_dir(path)?;
+                } else {
+                    self.status = Some(Status::error(format!(
+                        "{name}: {} isn't a directory",
+                        path.display()
+                    )));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn show_templates(&mut self) {
+        if self.archive.is_some() {
+            self.status = Some(Status::warning("archives are browsed read-only"));
+            return;
+        }
+        let Some(dir) = self.config.template_dir.clone() else {
+            self.status = Some(Status::warning(
+                "set template_dir in the config to use templates",
+            ));
+            return;
+        };
+        let mut items: Vec<PathBuf> = fs::read_dir(&dir)
+            .map(|rd| rd.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+            .unwrap_or_default();
+        if items.is_empty() {
+            self.status = Some(Status::warning(format!(
+                "no templates in {}",
+                dir.display()
+            )));
+            return;
+        }
+        items.sort();
+        self.templates = Some(TemplateView {
+            items,
+            state: ListState::default().with_selected(Some(0)),
+        });
+    }
+
+    fn handle_template_key(&mut self, code: KeyCode) {
+        let Some(view) = self.templates.as_mut() else {
+            return;
+        };
+        let len = view.items.len();
+        let sel = view.state.selected().unwrap_or(0);
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => view.state.select(Some((sel + 1) % len)),
+            KeyCode::Up | KeyCode::Char('k') => view.state.select(Some((sel + len - 1) % len)),
+            KeyCode::Esc => self.templates = None,
+            KeyCode::Enter => {
+                let template = view.items[sel].clone();
+                self.templates = None;
+                let name = file_name_of(&template);
+                self.ask_text(
+                    "new file name",
+                    &name,
+                    PromptAction::NewFromTemplate(template),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Copy `template` (a file or a whole directory) into cwd as `name`.
+    fn create_from_template(&mut self, template: &Path, name: &str) -> Result<()> {
+        if name.is_empty() {
+            return Ok(());
+        }
+        let dest = self.cwd.join(name);
+        if self.copy_new(template, &dest)? {
+            self.status = Some(Status::success(format!(
+                "created {name} from {}",
+                file_name_of(template)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Ask for a name to copy the highlighted entry to, in its own
+    /// directory.
+    fn start_duplicate(&mut self) {
+        let Some(e) = self.selected_entry() else {
+            return;
+        };
+        if self.archive.as_ref().is_some_and(|a| a.contains(&e.path)) {
+            self.status = Some(Status::warning("archives are browsed read-only"));
+            return;
+        }
+        let (path, name) = (e.path.clone(), file_name_of(&e.path));
+        self.ask_text("duplicate as", &name, PromptAction::Duplicate(path));
+    }
+
+    fn duplicate(&mut self, src: &Path, name: &str) -> Result<()> {
+        if name.is_empty() {
+            return Ok(());
+        }
+        let dest = src.parent().unwrap_or(&self.cwd).join(name);
+        if self.copy_new(src, &dest)? {
+            self.status = Some(Status::success(format!(
+                "duplicated {} as {name}",
+                file_name_of(src)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Copy `src` to `dest`, which mustn't exist yet, and highlight the
+    /// copy. Returns false, with the reason in the status, if nothing was
+    /// copied.
+    fn copy_new(&mut self, src: &Path, dest: &Path) -> Result<bool> {
+        let name = file_name_of(dest);
+        if dest.symlink_metadata().is_ok() {
+            self.status = Some(Status::error(format!("{name} already exists")));
+            return Ok(false);
+        }
+        if let Err(err) = batch::copy_recursive(src, dest) {
+            self.status = Some(Status::error(format!("couldn't create {name}: {err}")));
+            return Ok(false);
+        }
+        self.undo = Some(UndoOp::Created(vec![dest.to_path_buf()]));
+        self.reload_entries()?;
+        self.select_path(dest);
+        Ok(true)
+    }
+
+    fn toggle_info(&mut self) {
+        if self.info.is_some() {
+            self.info = None;
+        } else if let Some(e) = self.selected_entry() {
+            self.info = Some(entry_info(&e.path));
+        }
+    }
+
+    fn resize_preview(&mut self, delta: i16) {
+        let (min, max) = PREVIEW_SPLIT_RANGE;
+        self.preview_split = self
+            .preview_split
+            .saturating_add_signed(delta)
+            .clamp(min, max);
+        self.show_preview = true;
+    }
+
+    fn preview_target(&self) -> Option<PathBuf> {
+        self.preview_pinned
+            .clone()
+            .or_else(|| self.selected_entry().map(|e| e.path.clone()))
+    }
+
+    fn toggle_preview_pin(&mut self) {
+        if self.preview_pinned.take().is_some() {
+            self.status = Some(Status::info("preview follows selection"));
+        } else if let Some(e) = self.selected_entry() {
+            let (name, path) = (e.name.clone(), e.path.clone());
+            self.status = Some(Status::info(format!("preview pinned to {name}")));
+            self.preview_pinned = Some(path);
+            self.show_preview = true;
+        }
+    }
+
+    /// How far the preview of `path` is scrolled; 0 unless J/K moved it.
+    fn preview_offset(&self, path: &Path) -> usize {
+        match &self.preview_scroll {
+            Some((p, offset)) if p == path => *offset,
+            _ => 0,
+        }
+    }
+
+    /// Scroll the preview by `delta` lines, keeping at least its last line
+    /// in view.
+    fn scroll_preview(&mut self, delta: isize) {
+        if !self.show_preview {
+            self.status = Some(Status::info("P opens the preview"));
+            return;
+        }
+        let Some(target) = self.preview_target() else {
+            return;
+        };
+        let last = self
+            .preview_content(&target)
+            .lines()
+            .len()
+            .saturating_sub(1);
+        let offset = self
+            .preview_offset(&target)
+            .saturating_add_signed(delta)
+            .min(last);
+        self.preview_scroll = Some((target, offset));
+    }
+
+    /// The header's git summary for cwd, e.g. `main*`.
+    fn git_label(&mut self) -> Option<String> {
+        if !self.config.show_git || self.archive.is_some() {
+            return None;
+        }
+        self.git_states
+            .entry(self.cwd.clone())
+            .or_insert_with_key(|dir| git::state(dir))
+            .as_ref()
+            .map(git::RepoState::label)
+    }
+
+    /// `12.3 GiB free of 100.0 GiB` for the footer, when that's turned on,
+    /// after cwd's own size once `u` has summed it.
+    fn disk_label(&mut self) -> Option<String> {
+        if !self.config.show_disk_space || self.archive.is_some() {
+            return None;
+        }
+        if self
+            .disk_space
+            .as_ref()
+            .is_none_or(|(dir, _)| *dir != self.cwd)
+        {
+            self.disk_space = Some((self.cwd.clone(), disk::space(&self.cwd)));
+        }
+        let (_, space) = self.disk_space.as_ref()?;
+        let free = space
+            .as_ref()
+            .map(|s| format!("{} free of {}", human_size(s.free), human_size(s.total)))?;
+        Some(match &self.last_dir_size {
+            Some((dir, t)) if *dir == self.cwd => {
+                format!("{} here  |  {free}", human_size(t.bytes))
+            }
+            _ => free,
+        })
+    }
+
+    /// Preview content for `path`, reloading only when the path changes.
+    fn preview_content(&mut self, path: &Path) -> &preview::Content {
+        if self.preview_cache.as_ref().is_none_or(|(p, _)| p != path) {
+            let content = match &self.archive {
+                // Members aren't extracted, but directories can be listed
+                Some(a) if a.contains(path) => preview::Content::Lines(match a.read_dir(path) {
+                    Ok(entries) if path == a.path || !entries.is_empty() => entries
+                        .iter()
+                        .map(|e| format!("{}{}", e.name, if e.is_dir() { "/" } else { "" }))
+                        .collect(),
+                    _ => vec!["<inside archive>".to_string()],
+                }),
+                _ => preview::load(path),
+            };
+            self.preview_cache = Some((path.to_path_buf(), content));
+        }
+        &self.preview_cache.as_ref().expect("filled in above").1
+    }
+
+    fn start_args_prompt(&mut self) {
+        if let Some(e) = self.selected_entry()
+            && !e.is_dir()
+        {
+            let path = e.path.clone();
+            self.ask_text("editor args", "", PromptAction::EditorArgs(path));
+        }
+    }
+
+    fn start_line_prompt(&mut self) {
+        if let Some(e) = self.selected_entry()
+            && !e.is_dir()
+        {
+            let path = e.path.clone();
+            self.ask_text("open at line[:col]", "", PromptAction::OpenAt(path));
+        }
+    }
+
+    /// Put a text prompt up, pre-filled with `initial`; `action` gets the
+    /// text on Enter.
+    fn ask_text(&mut self, label: &'static str, initial: &str, action: PromptAction) {
+        self.prompt = Some(Prompt {
+            label,
+            input: TextInput::new(initial),
+            action,
+        });
+    }
+
+    fn handle_prompt_key(&mut self, key: KeyEvent) -> Result<()> {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return Ok(());
+        };
+        if prompt.input.handle(key) {
+            return Ok(());
+        }
+        match key.code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Enter => {
+                if let Some(Prompt { input, action, .. }) = self.prompt.take() {
+                    self.submit_prompt(action, input.value())?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn submit_prompt(&mut self, action: PromptAction, text: &str) -> Result<()> {
+        match action {
+            PromptAction::EditorArgs(path) => {
+                let args = text.split_whitespace().map(String::from).collect();
+                self.open_file(OpenRequest {
+                    paths: vec![path],
+                    args,
+                    opener: Opener::Editor,
+                    at: None,
+                })
+            }
+            PromptAction::Grep => {
+                self.grep(text);
+                Ok(())
+            }
+            PromptAction::MarkByAge => {
+                let text = text.trim();
+                let (older, days) = match text.strip_prefix('+') {
+                    Some(days) => (true, days),
+                    None => (false, text.strip_prefix('-').unwrap_or(text)),
+                };
+                match days.parse() {
+                    Ok(days) => self.mark_by_age(days, older),
+                    Err(_) => {
+                        self.status = Some(Status::error(format!("not a number of days: {text}")))
+                    }
+                }
+                Ok(())
+            }
+            PromptAction::Export => {
+                if text.is_empty() {
+                    return Ok(());
+                }
+                let dest = self.cwd.join(text);
+                if dest.symlink_metadata().is_ok() {
+                    self.ask(
+                        format!("{} exists. Overwrite it?", file_name_of(&dest)),
+                        ConfirmAction::Export(dest),
+                    )?;
+                } else {
+                    self.export_listing(&dest);
+                }
+                Ok(())
+            }
+            PromptAction::NewFromTemplate(template) => self.create_from_template(&template, text),
+            PromptAction::Duplicate(src) => self.duplicate(&src, text),
+            PromptAction::GoTo => self.jump_to_path(text),
+            PromptAction::MarkLargerThan => {
+                match parse_size(text) {
+                    Some(min) => self.mark_larger_than(min),
+                    None => self.status = Some(Status::error(format!("not a size: {text}"))),
+                }
+                Ok(())
+            }
+            PromptAction::OpenAt(path) => match Position::parse(text) {
+                Some(at) => self.open_file(OpenRequest {
+                    at: Some(at),
+                    ..OpenRequest::single(path, Opener::Editor)
+                }),
+                None => {
+                    self.status = Some(Status::error(format!("not a line number: {text}")));
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    /// Put a yes/no question up; `action` runs only if the answer is yes.
+    /// Under `force`, destructive actions run straight away instead; the
+    /// other questions aren't about safety, so they're still asked.
+    fn ask(&mut self, message: String, action: ConfirmAction) -> Result<()> {
+        if self.force && action.is_destructive() {
+            return self.run_confirmed(action);
+        }
+        self.confirm = Some(Confirm { message, action });
+        Ok(())
+    }
+
+    fn toggle_force(&mut self) {
+        self.force = !self.force;
+        self.status = Some(if self.force {
+            Status::warning("force: deleting and overwriting without asking")
+        } else {
+            Status::info("force off: asking before deleting and overwriting")
+        });
+    }
+
+    /// Answer the pending question, running or dropping its action.
+    fn resolve_confirm(&mut self, confirmed: bool) -> Result<()> {
+        let Some(Confirm { action, .. }) = self.confirm.take() else {
+            return Ok(());
+        };
+        if !confirmed {
+            self.status = Some(Status::info(format!("{} cancelled", action.name())));
+            return Ok(());
+        }
+        self.run_confirmed(action)
+    }
+
+    fn run_confirmed(&mut self, action: ConfirmAction) -> Result<()> {
+        match action {
+            ConfirmAction::Open(req) => self.launch(&req)?,
+            ConfirmAction::Delete(paths) => {
+                self.batch = Some(batch::Job::spawn(batch::Op::Delete, paths, PathBuf::new()));
+            }
+            ConfirmAction::Export(dest) => self.export_listing(&dest),
+        }
+        Ok(())
+    }
+
+    /// The marked paths, or just the highlighted one when nothing is marked.
+    fn mark_or_highlight(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.selected_paths.iter().cloned().collect();
+        paths.sort();
+        if paths.is_empty()
+            && let Some(e) = self.selected_entry()
+        {
+            paths.push(e.path.clone());
+        }
+        paths
+    }
+
+    fn start_delete(&mut self) -> Result<()> {
+        let paths = self.mark_or_highlight();
+        let what = match paths.as_slice() {
+            [] => return Ok(()),
+            [path] => file_name_of(path),
+            paths => format!("{} items", paths.len()),
+        };
+        if paths.len() <= self.config.confirm_delete_above {
+            self.batch = Some(batch::Job::spawn(batch::Op::Delete, paths, PathBuf::new()));
+            return Ok(());
+        }
+        self.ask(
+            format!("Delete {what} permanently?"),
+            ConfirmAction::Delete(paths),
+        )
+    }
+
+    /// Copy or move the marked entries into cwd.
+    fn start_transfer(&mut self, op: batch::Op) {
+        let mut paths: Vec<PathBuf> = self.selected_paths.iter().cloned().collect();
+        if paths.is_empty() {
+            self.status = Some(Status::warning(format!(
+                "mark entries to {} them here",
+                op.verb()
+            )));
+            return;
+        }
+        paths.sort();
+        self.batch = Some(batch::Job::spawn(op, paths, self.cwd.clone()));
+    }
+
+    /// Pick up batch progress; once it's over, move marks along with moved
+    /// paths, drop those on deleted ones and summarize. Called every tick.
+    fn poll_batch(&mut self) -> Result<()> {
+        let Some(job) = &mut self.batch else {
+            return Ok(());
+        };
+        job.poll();
+        if !job.finished {
+            return Ok(());
+        }
+        let job = self.batch.take().expect("checked above");
+        self.disk_space = None;
+        self.last_dir_size = None;
+        if job.op != batch::Op::Copy {
+            for (src, res) in &job.results {
+                if let Ok(dest) = res {
+                    self.carry_mark(src, dest.as_deref());
+                }
+            }
+        }
+        let done = job.results.len() - job.failures();
+        if done > 0 {
+            // (source, where it ended up) for each copy or move that worked
+            let landed = job
+                .results
+                .iter()
+                .filter_map(|(src, res)| Some((src.clone(), res.as_ref().ok()?.clone()?)));
+            self.undo = Some(match job.op {
+                batch::Op::Delete => UndoOp::Deleted,
+                batch::Op::Copy => UndoOp::Created(landed.map(|(_, dest)| dest).collect()),
+                batch::Op::Move => UndoOp::Moved(landed.collect()),
+            });
+        }
+        let mut msg = format!("{} {done} of {}", job.op.past(), job.total);
+        if job.cancelled && job.results.len() < job.total {
+            msg.push_str(" (cancelled)");
+        }
+        if let Some((src, Err(e))) = job.results.iter().find(|(_, r)| r.is_err()) {
+            let name = src.file_name().unwrap_or_default().to_string_lossy();
+            msg.push_str(&format!("; {} failed, e.g. {name}: {e}", job.failures()));
+        }
+        self.reload_entries()?;
+        self.status = Some(match (job.failures(), done) {
+            (0, _) if !job.cancelled => Status::success(msg),
+            (_, 0) => Status::error(msg),
+            _ => Status::warning(msg),
+        });
+        Ok(())
+    }
+
+    /// Reverse the last move, copy or duplicate: move the paths back or
+    /// remove the copies. Anything that's been put in the way since is left
+    /// alone and counted as failed.
+    fn undo(&mut self) -> Result<()> {
+        let (what, results) = match self.undo.take() {
+            None => {
+                self.status = Some(Status::info("nothing to undo"));
+                return Ok(());
+            }
+            Some(UndoOp::Deleted) => {
+                self.status = Some(Status::warning(
+                    "the last delete was permanent and can't be undone",
+                ));
+                return Ok(());
+            }
+            Some(UndoOp::Moved(moves)) => {
+                let mut results = Vec::new();
+                for (from, to) in moves {
+                    let res = if from.symlink_metadata().is_ok() {
+                        Err(format!("{} exists again", from.display()))
+                    } else {
+                        batch::move_path(&to, &from).map_err(|e| e.to_string())
+                    };
+                    if res.is_ok() {
+                        self.carry_mark(&to, Some(&from));
+                    }
+                    results.push((to, res));
+                }
+                ("moved back", results)
+            }
+            Some(UndoOp::Created(paths)) => {
+                let mut results = Vec::new();
+                for path in paths {
+                    let res = batch::remove(&path).map_err(|e| e.to_string());
+                    if res.is_ok() {
+                        self.carry_mark(&path, None);
+                    }
+                    results.push((path, res));
+                }
+                ("removed", results)
+            }
+        };
+        self.disk_space = None;
+        self.last_dir_size = None;
+        let failed: Vec<_> = results.iter().filter(|(_, r)| r.is_err()).collect();
+        let done = results.len() - failed.len();
+        let mut msg = format!("undo: {what} {done} of {}", results.len());
+        if let Some((path, Err(e))) = failed.first() {
+            msg.push_str(&format!(
+                "; {} failed, e.g. {}: {e}",
+                failed.len(),
+                file_name_of(path)
+            ));
+        }
+        self.status = Some(match (failed.len(), done) {
+            (0, _) => Status::success(msg),
+            (_, 0) => Status::error(msg),
+            _ => Status::warning(msg),
+        });
+        self.reload_entries()
+    }
+
+    fn toggle_mark(&mut self) {
+        if let Some(e) = self.selected_entry() {
+            let marked = self.selected_paths.contains(&e.path);
+            let e = e.clone();
+            self.set_marked(&e, !marked);
+            if self.group_marked {
+                self.rebuild_view();
+            }
+        }
+    }
+
+    /// Toggle the highlighted mark and step down to the entry below it, for
+    /// marking a run of entries. The step follows the entry rather than the
+    /// row, since grouping marked entries reorders the list; at the bottom
+    /// it stays put instead of wrapping.
+    fn toggle_mark_and_next(&mut self) {
+        let below = self
+            .selected_index()
+            .and_then(|i| self.entries.get(i + 1))
+            .map(|e| e.path.clone());
+        self.toggle_mark();
+        if let Some(path) = below {
+            self.select_path(&path);
+        }
+    }
+
+    /// `from` was moved to `to`, or deleted if that's `None`: wherever it
+    /// was marked, in any register, move the mark along or drop it.
+    fn carry_mark(&mut self, from: &Path, to: Option<&Path>) {
+        let active = (&mut self.selected_paths, &mut self.marked_sizes);
+        let parked = self.registers.values_mut().map(|(p, s)| (p, s));
+        for (paths, sizes) in std::iter::once(active).chain(parked) {
+            if !paths.remove(from) {
+                continue;
+            }
+            let size = sizes.remove(from);
+            if let Some(to) = to {
+                paths.insert(to.to_path_buf());
+                sizes.insert(to.to_path_buf(), size.unwrap_or(0));
+            }
+        }
+    }
+
+    fn set_marked(&mut self, e: &Entry, marked: bool) {
+        if marked {
+            // Directories don't count towards the marked size
+            let size = if e.is_dir() { 0 } else { e.size };
+            self.selected_paths.insert(e.path.clone());
+            self.marked_sizes.insert(e.path.clone(), size);
+        } else {
+            self.selected_paths.remove(&e.path);
+            self.marked_sizes.remove(&e.path);
+        }
+    }
+
+    /// Make `name` the active mark register, parking the current marks
+    /// under the old one. Only the active register is marked, toggled and
+    /// printed on exit.
+    fn switch_register(&mut self, name: char) {
+        if name != DEFAULT_REGISTER && !name.is_ascii_lowercase() {
+            self.status = Some(Status::error(format!(
+                "no register {name:?}; use a-z, or \" for the default"
+            )));
+            return;
+        }
+        if name != self.register {
+            let parked = (
+                std::mem::take(&mut self.selected_paths),
+                std::mem::take(&mut self.marked_sizes),
+            );
+            self.registers.insert(self.register, parked);
+            (self.selected_paths, self.marked_sizes) =
+                self.registers.remove(&name).unwrap_or_default();
+            self.register = name;
+            if self.group_marked {
+                self.rebuild_view();
+            }
+        }
+        self.status = Some(Status::info(format!(
+            "register \"{name}: {} marked",
+            self.selected_paths.len()
+        )));
+    }
+
+    /// Mark every visible file sharing the highlighted file's extension, or
+    /// unmark them all if they already are.
+    fn toggle_mark_same_extension(&mut self) {
+        let Some(e) = self.selected_entry().filter(|e| !e.is_dir()) else {
+            return;
+        };
+        let ext = split_extension(&e.name).1.to_lowercase();
+        if ext.is_empty() {
+            self.status = Some(Status::warning("highlighted file has no extension"));
+            return;
+        }
+        let same: Vec<Entry> = self
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir() && split_extension(&e.name).1.to_lowercase() == ext)
+            .cloned()
+            .collect();
+        let mark = !same.iter().all(|e| self.selected_paths.contains(&e.path));
+        for e in &same {
+            self.set_marked(e, mark);
+        }
+        let verb = if mark { "marked" } else { "unmarked" };
+        self.status = Some(Status::info(format!("{verb} {} .{ext} files", same.len())));
+        if self.group_marked {
+            self.rebuild_view();
+        }
+    }
+
+    fn copy_cwd(&mut self) {
+        let cwd = fs::canonicalize(&self.cwd).unwrap_or_else(|_| self.cwd.clone());
+        let text = cwd.display().to_string();
+        self.status = Some(match clipboard::copy(text.as_bytes()) {
+            Ok(via) => Status::success(format!("copied {text} (via {via})")),
+            Err(err) => Status::error(format!("copy failed: {err}")),
+        });
+    }
+
+    /// Mark the visible files modified within the last `days` days, or
+    /// with `older`, the ones not modified for that long.
+    fn mark_by_age(&mut self, days: u64, older: bool) {
+        let cutoff = SystemTime::now()
+            .checked_sub(Duration::from_secs(days.saturating_mul(24 * 60 * 60)))
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let matching: Vec<Entry> = self
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir())
+            .filter(|e| e.modified.is_some_and(|t| (t < cutoff) == older))
+            .cloned()
+            .collect();
+        for e in &matching {
+            self.set_marked(e, true);
+        }
+        let which = if older {
+            "older than"
+        } else {
+            "modified within"
+        };
+        self.status = Some(Status::info(format!(
+            "marked {} files {which} {days} days",
+            matching.len()
+        )));
+        if self.group_marked {
+            self.rebuild_view();
+        }
+    }
+
+    /// Mark the visible files bigger than `min` bytes.
+    fn mark_larger_than(&mut self, min: u64) {
+        let matching: Vec<Entry> = self
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir() && e.size > min)
+            .cloned()
+            .collect();
+        for e in &matching {
+            self.set_marked(e, true);
+        }
+        self.status = Some(Status::info(format!(
+            "marked {} files larger than {}",
+            matching.len(),
+            human_size(min)
+        )));
+        if self.group_marked {
+            self.rebuild_view();
+        }
+    }
+
+    /// Copy the highlighted path relative to `--root` (or the start
+    /// directory), falling back to the absolute path if it isn't under it.
+    fn copy_relative_path(&mut self) {
+        let Some(e) = self.selected_entry() else {
+            return;
+        };
+        let base = self.root.as_ref().unwrap_or(&self.start_dir);
+        let rel = e
+            .path
+            .strip_prefix(base)
+            .map(Path::to_path_buf)
+            .or_else(|_| {
+                // Lexical mismatch (e.g. symlinked start dir) may still be
+                // under the base once both sides are canonical
+                let path = fs::canonicalize(&e.path)?;
+                let base = fs::canonicalize(base)?;
+                path.strip_prefix(&base)
+                    .map(Path::to_path_buf)
+                    .map_err(io::Error::other)
+            })
+            .unwrap_or_else(|_| e.path.clone());
+        let text = rel.display().to_string();
+        self.status = Some(match clipboard::copy(text.as_bytes()) {
+            Ok(via) => Status::success(format!("copied {text} (via {via})")),
+            Err(err) => Status::error(format!("copy failed: {err}")),
+        });
+    }
+
+    /// Write the visible listing to `dest`, one name per line in the
+    /// current order. Shown metadata columns come along tab-separated: the
+    /// time column as an absolute `date_format` time, the inode column as
+    /// inode and link count.
+    fn export_listing(&mut self, dest: &Path) {
+        let mut text = String::new();
+        for e in &self.entries {
+            if self.show_full_paths {
+                text.push_str(&e.path.to_string_lossy());
+            } else {
+                text.push_str(&e.name);
+            }
+            if self.time_column != TimeColumn::Hidden {
+                let time = e.modified.map(|t| {
+                    DateTime::<Local>::from(t)
+                        .format(&self.config.date_format)
+                        .to_string()
+                });
+                text.push_str(&format!("\t{}", time.unwrap_or_default()));
+            }
+            if self.show_inode_column {
+                let num = |n: Option<u64>| n.map(|n| n.to_string()).unwrap_or_default();
+                text.push_str(&format!("\t{}\t{}", num(e.inode), num(e.nlinks)));
+            }
+            text.push('\n');
+        }
+        self.status = Some(match fs::write(dest, text) {
+            Ok(()) => Status::success(format!(
+                "wrote {} entries to {}",
+                self.entries.len(),
+                dest.display()
+            )),
+            Err(err) => Status::error(format!("couldn't write {}: {err}", dest.display())),
+        });
+    }
+
+    /// Put the highlighted file's text itself on the clipboard. Binary
+    /// files and anything over `MAX_CLIPBOARD_BYTES` are refused.
+    fn copy_contents(&mut self) {
+        let Some(e) = self.selected_entry() else {
+            return;
+        };
+        let name = e.name.clone();
+        if e.kind != Kind::File || self.archive.is_some() {
+            self.status = Some(Status::error(format!("{name} is not a regular file")));
+            return;
+        }
+        if e.size > MAX_CLIPBOARD_BYTES {
+            self.status = Some(Status::error(format!(
+                "{name} is {}; only files up to {} are copied",
+                human_size(e.size),
+                human_size(MAX_CLIPBOARD_BYTES)
+            )));
+            return;
+        }
+        let data = match fs::read(&e.path) {
+            Ok(data) => data,
+            Err(err) => {
+                self.status = Some(Status::error(format!("can't read {name}: {err}")));
+                return;
+            }
+        };
+        if preview::looks_binary(&data) {
+            self.status = Some(Status::error(format!(
+                "{name} looks binary; not copying it"
+            )));
+            return;
+        }
+        self.status = Some(match clipboard::copy(&data) {
+            Ok(via) => Status::success(format!(
+                "copied contents of {name}, {} (via {via})",
+                human_size(data.len() as u64)
+            )),
+            Err(err) => Status::error(format!("copy failed: {err}")),
+        });
+    }
+
+    fn show_absolute_path(&mut self) {
+        if let Some(e) = self.selected_entry() {
+            // Canonicalization fails for e.g. broken symlinks; fall back to cwd + name
+            let abs = fs::canonicalize(&e.path).unwrap_or_else(|_| self.cwd.join(&e.name));
+            self.status = Some(Status::info(abs.display().to_string()));
+        }
+    }
+
+    /// Start summing the highlighted directory (or the cwd) in the background.
+    fn start_dir_size(&mut self) {
+        if let Some(job) = &self.dir_size {
+            job.cancel();
+        }
+        let dir = match self.selected_entry() {
+            Some(e) if e.is_dir() => e.path.clone(),
+            _ => self.cwd.clone(),
+        };
+        self.dir_size = Some(dirsize::Job::spawn(dir));
+        self.poll_dir_size();
+    }
+
+    fn toggle_dir_counts(&mut self) {
+        self.fs_source.probes.counts = !self.fs_source.probes.counts;
+        self.reload_or_report();
+    }
+
+    fn toggle_auto_refresh(&mut self) {
+        self.auto_refresh = match self.auto_refresh {
+            Some(_) => None,
+            None => Some(Duration::from_secs(match self.config.auto_refresh_secs {
+                0 => DEFAULT_AUTO_REFRESH_SECS,
+                secs => secs,
+            })),
+        };
+        self.last_refresh = Instant::now();
+    }
+
+    /// Re-read the listing if auto-refresh is on and it's due; called every
+    /// tick. Waits while a batch or question is up, since those hold paths
+    /// from the current listing. Nobody asked for this read, so failing it
+    /// (say cwd became unreadable) is only reported; the next tick retries.
+    fn poll_auto_refresh(&mut self) {
+        let Some(every) = self.auto_refresh else {
+            return;
+        };
+        // Nor is a walk that hasn't finished restarted
+        if self.last_refresh.elapsed() < every
+            || self.batch.is_some()
+            || self.confirm.is_some()
+            || self.flatten_job.is_some()
+        {
+            return;
+        }
+        self.last_refresh = Instant::now();
+        if let Err(err) = self.reload_entries() {
+            self.status = Some(Status::error(format!("auto-refresh: {err:#}")));
+        }
+    }
+
+    /// Surface the running total in the status line; called every tick.
+    fn poll_dir_size(&mut self) {
+        let Some(job) = &mut self.dir_size else {
+            return;
+        };
+        job.poll();
+        let name = job.dir.file_name().map_or_else(
+            || job.dir.display().to_string(),
+            |n| n.to_string_lossy().into_owned(),
+        );
+        let t = job.totals;
+        if job.done {
+            self.status = Some(Status::success(format!(
+                "{name}: {} in {} files",
+                human_size(t.bytes),
+                t.files
+            )));
+            self.last_dir_size = Some((job.dir.clone(), t));
+            self.dir_size = None;
+        } else {
+            self.status = Some(Status::info(format!(
+                "sizing {name}: {} in {} files so far (Esc cancels)",
+                human_size(t.bytes),
+                t.files
+            )));
+        }
+    }
+
+    fn cancel_dir_size(&mut self) {
+        if let Some(job) = self.dir_size.take() {
+            job.cancel();
+            self.status = Some(Status::info("size calculation cancelled"));
+        }
+    }
+
+    fn marked_size(&self) -> u64 {
+        self.marked_sizes.values().sum()
+    }
+
+    /// Bounce back to the previous directory, like `cd -`.
+    fn swap_dir(&mut self) -> Result<()> {
+        let Some((dir, highlighted)) = self.prev_dir.clone() else {
+            self.status = Some(Status::warning("no previous directory"));
+            return Ok(());
+        };
+        self.change_dir(dir)?;
+        if let Some(path) = highlighted {
+            self.select_path(&path);
+        }
+        Ok(())
+    }
+
+    fn pin_project_root(&mut self) {
+        self.status = Some(Status::success(format!(
+            "project root: {} (B jumps back)",
+            self.cwd.display()
+        )));
+        self.project_root = Some(self.cwd.clone());
+    }
+
+    /// Jump to the pinned directory. Coming back from somewhere below it
+    /// highlights the entry we came up through.
+    fn goto_project_root(&mut self) -> Result<()> {
+        let Some(root) = self.project_root.clone() else {
+            self.status = Some(Status::warning(
+                "no project root yet; b pins the current directory",
+            ));
+            return Ok(());
+        };
+        let came_from = self.cwd.clone();
+        self.change_dir(root.clone())?;
+        if let Ok(rest) = came_from.strip_prefix(&root)
+            && let Some(first) = rest.components().next()
+        {
+            self.select_path(&root.join(first));
+        }
+        Ok(())
+    }
+
+    /// Climb `levels` directories at once, stopping at the filesystem root
+    /// or `--root`, whichever comes first.
+    fn up_dir(&mut self, levels: usize) -> Result<()> {
+        let target = self
+            .cwd
+            .ancestors()
+            .skip(1)
+            .take(levels)
+            .take_while(|dir| self.within_root(dir))
+            .last()
+            .map(Path::to_path_buf);
+        match target {
+            Some(dir) => self.change_dir(dir),
+            // Already at the top; change_dir says why for --root
+            None => match self.cwd.parent() {
+                Some(parent) => self.change_dir(parent.to_path_buf()),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let mut args = Args::parse()?;
+    let mut start_dir = env::current_dir()?;
+    if let Some(root) = &args.root {
+        let root = fs::canonicalize(root)
+            .with_context(|| format!("resolving --root {}", root.display()))?;
+        if !fs::canonicalize(&start_dir).is_ok_and(|d| d.starts_with(&root)) {
+            start_dir = root.clone();
+        }
+        args.root = Some(root);
+    }
+    let config = Config::load();
+    let cancel_exit_code = config.cancel_exit_code;
+    // Setup terminal
+    if args.no_alt_screen {
+        term::disable_alt_screen();
+    }
+    enable_raw_mode()?;
+    let mut out = term::output().context("opening the terminal")?;
+    term::enter_alt_screen(&mut out)?;
+    term::enable_paste(&mut out)?;
+    let backend = ratatui::backend::CrosstermBackend::new(out);
+    let mut terminal = Terminal::new(backend)?;
+    terminal.clear()?;
+
+    let res = App::new(start_dir, config, &args).and_then(|app| run_app(&mut terminal, app, &args));
+
+    // Restore
+    disable_raw_mode()?;
+    term::disable_paste(terminal.backend_mut())?;
+    term::leave_alt_screen(terminal.backend_mut())?;
+    terminal.show_cursor()?;
+
+    match res {
+        Ok(Outcome::Accepted(paths)) => {
+            for path in paths {
+                println!("{}", path.display());
+            }
+        }
+        Ok(Outcome::Cancelled) => std::process::exit(cancel_exit_code),
+        Err(e) => {
+            eprintln!("error: {e:?}");
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn run_app(
+    terminal: &mut Terminal<ratatui::backend::CrosstermBackend<term::TermOut>>,
+    mut app: App,
+    args: &Args,
+) -> Result<Outcome> {
+    let accept = loop {
+        if let Some(path) = app.picked.take() {
+            return Ok(Outcome::Accepted(vec![path]));
+        }
+        app.poll_dir_size();
+        app.poll_grep();
+        app.poll_flatten();
+        app.poll_batch()?;
+        app.poll_auto_refresh();
+        terminal.draw(|f| ui(f, &mut app))?;
+
+        // Use poll so we can redraw at intervals if needed (smooth resize, etc.)
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        let k = match event::read()? {
+            Event::Key(k) => k,
+            Event::Paste(text) => {
+                app.status = None;
+                app.handle_paste(&text)?;
+                continue;
+            }
+            _ => continue,
+        };
+        // Ignore repeat events on key hold for some terminals
+        if k.kind == KeyEventKind::Release {
+            continue;
+        }
+        // Ctrl-c cancels from anywhere, prompts and overlays included
+        if k.code == KeyCode::Char('c') && k.modifiers.contains(KeyModifiers::CONTROL) {
+            if let Some(job) = &mut app.batch {
+                job.cancel();
+            }
+            return Ok(Outcome::Cancelled);
+        }
+        // Status messages only live until the next key press
+        app.status = None;
+        if let Some(job) = &mut app.batch {
+            if k.code == KeyCode::Esc {
+                job.cancel();
+            }
+            continue;
+        }
+        if app.confirm.is_some() {
+            let confirmed = matches!(k.code, KeyCode::Char('y') | KeyCode::Char('Y'));
+            app.resolve_confirm(confirmed)?;
+            continue;
+        }
+        if app.prompt.is_some() {
+            app.handle_prompt_key(k)?;
+            continue;
+        }
+        if app.filtering {
+            app.handle_filter_key(k)?;
+            continue;
+        }
+        if app.awaiting_register {
+            app.awaiting_register = false;
+            if let KeyCode::Char(c) = k.code {
+                app.switch_register(c);
+            }
+            continue;
+        }
+        if app.searching {
+            app.handle_search_key(k);
+            continue;
+        }
+        if app.recent.is_some() {
+            app.handle_recent_key(k.code)?;
+            continue;
+        }
+        if app.grep.is_some() {
+            app.handle_grep_key(k.code)?;
+            continue;
+        }
+        if app.templates.is_some() {
+            app.handle_template_key(k.code);
+            continue;
+        }
+        if app.places.as_ref().is_some_and(|p| p.focused) {
+            app.handle_places_key(k)?;
+            continue;
+        }
+        if app.info.is_some() {
+            if matches!(k.code, KeyCode::Esc | KeyCode::Char('i')) {
+                app.info = None;
+            }
+            continue;
+        }
+        if let KeyCode::Char(c) = k.code
+            && let Some(digit) = c.to_digit(10)
+            && (digit > 0 || app.count.is_some())
+        {
+            let count = app
+                .count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit as usize);
+            app.count = Some(count);
+            app.status = Some(Status::info(format!("{count}")));
+            continue;
+        }
+        let count = app.count.take();
+        match k.code {
+            // Esc first cancels a running size walk, search or flatten,
+            // then drops an active filter, then quits
+            KeyCode::Esc if app.dir_size.is_some() => app.cancel_dir_size(),
+            KeyCode::Esc if app.grep_job.is_some() => app.cancel_grep(),
+            KeyCode::Esc if app.flatten_job.is_some() => app.cancel_flatten()?,
+            KeyCode::Esc if !app.filter.is_empty() => app.clear_filter(),
+            KeyCode::Char('q') | KeyCode::Esc => break false,
+            KeyCode::Char('Q') => break true,
+            KeyCode::Down | KeyCode::Char('j') => app.next(),
+            KeyCode::Up | KeyCode::Char('k') => app.prev(),
+            KeyCode::Tab => app.move_to_dir(1),
+            KeyCode::BackTab => app.move_to_dir(-1),
+            KeyCode::Backspace => app.up_dir(count.unwrap_or(1))?,
+            KeyCode::Char('r') if k.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.refresh_all()?
+            }
+            KeyCode::Char('w') if k.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.toggle_auto_refresh()
+            }
+            KeyCode::Char('n') if k.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.show_templates()
+            }
+            KeyCode::Char('b') if k.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.toggle_places()
+            }
+            KeyCode::Char('z') if k.modifiers.contains(KeyModifiers::CONTROL) => app.undo()?,
+            KeyCode::Char('r') => app.reload_or_report(),
+            KeyCode::Char(' ') => app.toggle_mark(),
+            KeyCode::Char('m') => app.toggle_mark_and_next(),
+            KeyCode::Char('l') | KeyCode::Right => app.enter_dir()?,
+            KeyCode::Char('u') => app.start_dir_size(),
+            KeyCode::Char('U') => app.toggle_dir_counts(),
+            KeyCode::Char('t') => app.toggle_flatten(),
+            KeyCode::Char('F') => app.show_type_suffix = !app.show_type_suffix,
+            KeyCode::Char('\\') => app.show_full_paths = !app.show_full_paths,
+            KeyCode::Char('d') => app.start_delete()?,
+            KeyCode::Char('!') => app.toggle_force(),
+            KeyCode::F(5) => app.start_transfer(batch::Op::Copy),
+            KeyCode::F(6) => app.start_transfer(batch::Op::Move),
+            KeyCode::Char('*') => app.toggle_mark_same_extension(),
+            KeyCode::Char('x') => app.show_ext_column = !app.show_ext_column,
+            KeyCode::Char('#') => app.show_index_column = !app.show_index_column,
+            KeyCode::Char('%') => app.show_inode_column = !app.show_inode_column,
+            KeyCode::Char('T') => app.time_column = app.time_column.next(),
+            KeyCode::Char('a') => app.show_absolute_path(),
+            KeyCode::Char('y') => app.copy_relative_path(),
+            KeyCode::Char('Y') => app.copy_cwd(),
+            KeyCode::Char('C') => app.copy_contents(),
+            KeyCode::Char('"') => app.awaiting_register = true,
+            KeyCode::Char('L') => app.ask_text(
+                "mark files larger than (e.g. 100M)",
+                "",
+                PromptAction::MarkLargerThan,
+            ),
+            KeyCode::Char('@') => app.ask_text(
+                "mark by age, days (-N newer, +N older)",
+                "-",
+                PromptAction::MarkByAge,
+            ),
+            KeyCode::Char('w') => app.emit_selected()?,
+            KeyCode::Char('M') => app.toggle_group_marked(),
+            KeyCode::Char('v') => app.density = app.density.next(),
+            KeyCode::Char('o') if k.modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(nested) = app.nested(args)? {
+                    let outcome = run_app(terminal, nested, args)?;
+                    // The nested session drew over everything
+                    terminal.clear()?;
+                    app.adopt_nested(outcome);
+                }
+            }
+            KeyCode::Char('o') => app.reveal()?,
+            KeyCode::Char('E') => app.start_args_prompt(),
+            KeyCode::Char(':') => app.start_line_prompt(),
+            KeyCode::Char('G') => app.ask_text("grep", "", PromptAction::Grep),
+            KeyCode::Char('b') => app.pin_project_root(),
+            KeyCode::Char('B') => app.goto_project_root()?,
+            KeyCode::Char('-') => app.swap_dir()?,
+            KeyCode::Char('f') => app.start_filter(),
+            KeyCode::Char('/') => app.start_search(),
+            KeyCode::Char('n') => app.jump_to_match(1, 1),
+            KeyCode::Char('N') => app.jump_to_match(1, -1),
+            KeyCode::Char('i') => app.toggle_info(),
+            KeyCode::Char('R') => app.show_recent(),
+            KeyCode::Char('s') => app.cycle_sort_mode(),
+            KeyCode::Char('S') => app.reverse_sort(),
+            KeyCode::Char('P') => app.show_preview = !app.show_preview,
+            KeyCode::Char('J') => app.scroll_preview(PREVIEW_SCROLL_STEP as isize),
+            KeyCode::Char('K') => app.scroll_preview(-(PREVIEW_SCROLL_STEP as isize)),
+            KeyCode::Char('<') => app.resize_preview(-5),
+            KeyCode::Char('>') => app.resize_preview(5),
+            KeyCode::Char('e') => app.toggle_opener(),
+            KeyCode::Char('I') => app.toggle_pattern_hidden(),
+            KeyCode::Char('.') => app.toggle_peek_hidden(),
+            KeyCode::Char('p') if k.modifiers.is_empty() => app.view()?,
+            KeyCode::Char('O') => app.open_with_system(),
+            KeyCode::Char('c') => app.start_duplicate(),
+            KeyCode::Char('g') => app.ask_text("go to", "", PromptAction::GoTo),
+            KeyCode::Char('X') => app.ask_text("export listing to", "", PromptAction::Export),
+            KeyCode::Char('A') => app.open_marked()?,
+            KeyCode::Char('=') => app.diff_marked()?,
+            KeyCode::Char('p') if k.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.toggle_preview_pin()
+            }
+            KeyCode::Enter => app.enter()?,
+            _ => {}
+        }
+    };
+    Ok(app.finish(accept))
+}
+
+/// The register marks go into until `"` picks another
+const DEFAULT_REGISTER: char = '"';
+
+/// Bigger files aren't copied to the clipboard by `C`
+const MAX_CLIPBOARD_BYTES: u64 = 256 * 1024;
+
+/// Auto-refresh interval when `auto_refresh_secs` doesn't set one
+const DEFAULT_AUTO_REFRESH_SECS: u64 = 2;
+
+const APP_TITLE: &str = "Ratatui File Picker";
+const KEY_HINT: &str =
+    "↑/↓ move  ␣ toggle  Enter open  ⌫ up  f filter  r refresh  x ext  a path  Q accept  q quit";
+const SHORT_KEY_HINT: &str = "q quit";
+
+fn ui(f: &mut Frame, app: &mut App) {
+    let size = f.size();
+
+    // Everything after the cwd, which gets whatever room this leaves
+    let summary = format!(
+        "{}{}  |  sort: {} {}{}  |  selected{}: {} ({})",
+        if app.filter.is_empty() {
+            String::new()
+        } else {
+            format!(" [filter: {}]", app.filter.value())
+        },
+        match (app.flatten, app.archive.is_some()) {
+            (true, true) => " [archive, flat]",
+            (false, true) => " [archive]",
+            (true, false) => " [flat]",
+            (false, false) => "",
+        },
+        app.sort_mode.label(),
+        if app.sort_reversed { "↑" } else { "↓" },
+        if app.opener == Opener::Pager {
+            "  |  view mode"
+        } else {
+            ""
+        },
+        if app.register == DEFAULT_REGISTER {
+            String::new()
+        } else {
+            format!(" \"{}", app.register)
+        },
+        app.selected_paths.len(),
+        human_size(app.marked_size())
+    );
+    let summary = match app.git_label() {
+        Some(git) => format!("{summary}  |  git: {git}"),
+        None => summary,
+    };
+    let summary = if app.depth > 0 {
+        format!("{summary}  |  nested {}", app.depth)
+    } else {
+        summary
+    };
+    let summary = if app.emitted > 0 {
+        format!("{summary}  |  emitted: {}", app.emitted)
+    } else {
+        summary
+    };
+    let summary = match app.auto_refresh {
+        Some(every) => format!("{summary}  |  auto-refresh {}s", every.as_secs()),
+        None => summary,
+    };
+    let force_tag = if app.force { "FORCE " } else { "" };
+    // Room left on the top border after the corners and the app title
+    let budget =
+        (size.width as usize).saturating_sub(2 + APP_TITLE.width() + 2 + force_tag.width() + 1);
+    // Degrade from the full key hint to a short one to none at all, and
+    // only then shorten the cwd
+    let cwd = app.cwd.display().to_string();
+    let header = [KEY_HINT, SHORT_KEY_HINT]
+        .iter()
+        .map(|hint| format!("cwd: {cwd}{summary}  |  {hint}"))
+        .find(|h| h.width() < budget)
+        .unwrap_or_else(|| {
+            let room = budget.saturating_sub("cwd: ".width() + summary.width() + 1);
+            format!("cwd: {}{summary}", shorten_path(&cwd, room))
+        });
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Title::from(Line::from(vec![
+            Span::raw(" "),
+            Span::styled(APP_TITLE, Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(" "),
+            Span::styled(
+                force_tag,
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+        ])))
+        .title(
+            Title::from(Line::from(vec![Span::raw(" "), Span::raw(header)]))
+                .alignment(Alignment::Right),
+        )
+        .border_type(BorderType::Rounded);
+
+    let inner = block.inner(size);
+    f.render_widget(block, size);
+
+    let toggles = active_toggles(app);
+    let disk = app.disk_label();
+    let footer_height = if toggles.is_empty() && disk.is_none() {
+        0
+    } else {
+        1
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(footer_height),
+        ])
+        .split(inner);
+    f.render_widget(
+        Paragraph::new(toggles).style(Style::default().fg(Color::Cyan)),
+        chunks[2],
+    );
+    if let Some(disk) = disk {
+        f.render_widget(
+            Paragraph::new(disk)
+                .style(Style::default().fg(Color::DarkGray))
+                .alignment(Alignment::Right),
+            chunks[2],
+        );
+    }
+    let main = if app.places.is_some() {
+        let widest = app.config.places.iter().map(|(name, _)| name.width()).max();
+        let width = (widest.unwrap_or(0) + app.config.highlight_symbol.width() + 1)
+            .min(chunks[0].width as usize / 3);
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(width as u16), Constraint::Min(0)])
+            .split(chunks[0]);
+        render_places(f, app, panes[0]);
+        panes[1]
+    } else {
+        chunks[0]
+    };
+    let area = if app.show_preview {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(app.preview_split),
+                Constraint::Percentage(100 - app.preview_split),
+            ])
+            .split(main);
+        render_preview(f, app, panes[1]);
+        panes[0]
+    } else {
+        main
+    };
+
+    // Width of the right-aligned extension column, if shown, measured on
+    // the same text the rows split
+    let ext_width = if app.show_ext_column {
+        app.entries
+            .iter()
+            .filter(|e| !e.is_dir())
+            .map(|e| split_extension(shown_name(app, e)).1.width())
+            .max()
+            .unwrap_or(0)
+    } else {
+        0
+    };
+
+    // Only build items for the rows that fit in the viewport; we track the
+    // scroll offset ourselves so huge directories don't allocate a ListItem
+    // per entry every frame
+    let item_height = if app.density == Density::Comfortable {
+        2
+    } else {
+        1
+    };
+    let visible = (area.height as usize / item_height).max(1);
+    let selected = app.list_state.selected();
+    let mut offset = app
+        .list_state
+        .offset()
+        .min(app.entries.len().saturating_sub(1));
+    if let Some(sel) = selected {
+        if sel < offset {
+            offset = sel;
+        } else if sel >= offset + visible {
+            offset = sel + 1 - visible;
+        }
+    }
+    *app.list_state.offset_mut() = offset;
+    let end = (offset + visible).min(app.entries.len());
+
+    let time_width = app.time_column.width(&app.config.date_format);
+
+    // Inode and link count, each sized for its largest value in the listing;
+    // digits are counted rather than formatted, this being every entry
+    let (inode_width, links_width) = if app.show_inode_column {
+        let widest = |f: fn(&Entry) -> Option<u64>| {
+            app.entries
+                .iter()
+                .filter_map(f)
+                .max()
+                .map_or(0, |n| n.checked_ilog10().map_or(1, |d| d as usize + 1))
+        };
+        (widest(|e| e.inode), widest(|e| e.nlinks))
+    } else {
+        (0, 0)
+    };
+
+    // Sized for the largest index so the column doesn't shift while scrolling
+    let index_width = if app.show_index_column {
+        app.entries.len().to_string().len()
+    } else {
+        0
+    };
+
+    // When the names on screen leave too little room, the right-hand columns
+    // go one at a time: inode first, then the extension, then the time. The
+    // name column always stays
+    let name_width = app.entries[offset..end]
+        .iter()
+        .map(|e| shown_name(app, e).width() + name_extras(app, e).width())
+        .max()
+        .unwrap_or(0);
+    let lead_width = app.config.highlight_symbol.width()
+        + if index_width > 0 { index_width + 1 } else { 0 }
+        + if app.density == Density::Dense {
+            0
+        } else {
+            "○ 📄 ".width()
+        };
+    let inode_cols = if inode_width > 0 {
+        inode_width + 1 + links_width
+    } else {
+        0
+    };
+    let [inode_cols, ext_width, time_width] = fit_columns(
+        (area.width as usize).saturating_sub(lead_width),
+        name_width,
+        [inode_cols, ext_width, time_width],
+    );
+    let (inode_width, links_width) = if inode_cols > 0 {
+        (inode_width, links_width)
+    } else {
+        (0, 0)
+    };
+
+    let items: Vec<ListItem> = app.entries[offset..end]
+        .iter()
+        .enumerate()
+        .map(|(i, e)| {
+            let marked = app.selected_paths.contains(&e.path);
+            let prefix = if app.density == Density::Dense {
+                String::new()
+            } else {
+                let mark = if marked { "●" } else { "○" };
+                let icon = match (e.link, e.kind) {
+                    _ if e.locked => "🔒",
+                    (Some(_), _) => "🔗",
+                    (None, Kind::Dir) => "📁",
+                    (None, Kind::File) => "📄",
+                    (None, Kind::Fifo) => "🚰",
+                    (None, Kind::Socket) => "🔌",
+                    (None, Kind::BlockDevice) => "💽",
+                    (None, Kind::CharDevice) => "📟",
+                };
+                format!("{mark} {icon} ")
+            };
+            let shown = shown_name(app, e);
+            // A dropped extension column leaves the extension on the name
+            let (name, ext) = if ext_width > 0 && !e.is_dir() {
+                split_extension(shown)
+            } else {
+                (shown, "")
+            };
+            let mut name_style = if e.is_dir() {
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD)
+            } else if e.kind.is_special() {
+                Style::default().fg(Color::Magenta)
+            } else if app.config.color_executables && e.is_executable() {
+                Style::default().fg(Color::Green)
+            } else {
+                Style::default()
+            };
+            match e.link {
+                Some(LinkStatus::Valid) => name_style = name_style.add_modifier(Modifier::ITALIC),
+                Some(LinkStatus::Broken) => {
+                    name_style = name_style
+                        .fg(Color::Red)
+                        .add_modifier(Modifier::CROSSED_OUT)
+                }
+                None => {}
+            }
+            if e.locked {
+                name_style = name_style.fg(Color::DarkGray).add_modifier(Modifier::DIM);
+            }
+            if marked && app.density == Density::Dense {
+                // No marker circle in dense mode, so color marked names instead
+                name_style = name_style.fg(Color::Yellow);
+            }
+            let mut spans = Vec::new();
+            if index_width > 0 {
+                spans.push(Span::styled(
+                    format!("{:>index_width$} ", offset + i + 1),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            let prefix_width = prefix.width();
+            spans.push(Span::raw(prefix));
+            spans.extend(highlight_match(name, query_match(app, name), name_style));
+            let suffix = if app.show_type_suffix {
+                type_suffix(e)
+            } else {
+                ""
+            };
+            spans.push(Span::raw(suffix));
+            let badge = count_badge(app, e);
+            let badge_width = badge.width();
+            spans.push(Span::styled(badge, Style::default().fg(Color::DarkGray)));
+
+            // Right-aligned columns, each padded to a fixed width
+            let dim = Style::default().fg(Color::DarkGray);
+            let mut right = Vec::new();
+            let mut right_width = 0;
+            if ext_width > 0 {
+                let pad = ext_width.saturating_sub(ext.width());
+                right.push(Span::styled(format!("{}{ext}", " ".repeat(pad)), dim));
+                right_width += ext_width;
+            }
+            if inode_width > 0 {
+                let (inode, links) = match (e.inode, e.nlinks) {
+                    (Some(i), Some(n)) => (i.to_string(), n.to_string()),
+                    _ => (String::new(), String::new()),
+                };
+                // Several names for one file is the thing worth spotting
+                let links_style = if !e.is_dir() && e.nlinks.is_some_and(|n| n > 1) {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    dim
+                };
+                if right_width > 0 {
+                    right.push(Span::raw("  "));
+                    right_width += 2;
+                }
+                right.push(Span::styled(format!("{inode:>inode_width$} "), dim));
+                right.push(Span::styled(format!("{links:>links_width$}"), links_style));
+                right_width += inode_width + 1 + links_width;
+            }
+            if time_width > 0 {
+                let text = match (app.time_column, e.modified) {
+                    (TimeColumn::Relative, Some(t)) => format_age(t),
+                    (TimeColumn::Absolute, Some(t)) => DateTime::<Local>::from(t)
+                        .format(&app.config.date_format)
+                        .to_string(),
+                    _ => String::new(),
+                };
+                if right_width > 0 {
+                    right.push(Span::raw("  "));
+                    right_width += 2;
+                }
+                right.push(Span::styled(format!("{text:>time_width$}"), dim));
+                right_width += time_width;
+            }
+            if !right.is_empty() {
+                // Measured in terminal columns, not chars, so wide CJK
+                // names and emoji icons don't push the columns out of line
+                let index_col = if index_width > 0 { index_width + 1 } else { 0 };
+                let used = app.config.highlight_symbol.width()
+                    + index_col
+                    + prefix_width
+                    + name.width()
+                    + suffix.width()
+                    + badge_width
+                    + right_width;
+                let pad = (area.width as usize).saturating_sub(used).max(1);
+                spans.push(Span::raw(" ".repeat(pad)));
+                spans.extend(right);
+            }
+            let line = Line::from(spans);
+            if app.density == Density::Comfortable {
+                ListItem::new(vec![line, Line::default()])
+            } else {
+                ListItem::new(line)
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .highlight_symbol(&app.config.highlight_symbol)
+        .highlight_style(app.config.highlight_style);
+
+    // The rendered slice starts at `offset`, so shift the highlight into it
+    let mut view_state = ListState::default().with_selected(selected.map(|i| i - offset));
+    f.render_stateful_widget(list, area, &mut view_state);
+
+    if app.searching {
+        render_input(
+            f,
+            "/",
+            &app.search_query,
+            "  (Enter keep, Esc clear, n/N next/prev)",
+            chunks[1],
+        );
+    } else if app.filtering {
+        render_input(
+            f,
+            match (app.filter_is_regex, app.filter_case_sensitive) {
+                (false, false) => "filter: ",
+                (false, true) => "filter (Aa): ",
+                (true, false) => "filter (re): ",
+                (true, true) => "filter (re, Aa): ",
+            },
+            &app.filter,
+            if app.filter_is_regex && app.filter_regex.is_none() && !app.filter.is_empty() {
+                "  (invalid regex, not filtering)"
+            } else {
+                "  (Tab/Enter enters a unique dir match, Alt-c match case, Alt-r regex)"
+            },
+            chunks[1],
+        );
+    } else if let Some(prompt) = &app.prompt {
+        render_input(
+            f,
+            &format!("{}: ", prompt.label),
+            &prompt.input,
+            "",
+            chunks[1],
+        );
+    } else if let Some(confirm) = &app.confirm {
+        render_confirm(f, confirm, chunks[1]);
+    } else if let Some(status) = &app.status {
+        f.render_widget(
+            Paragraph::new(status.text.as_str()).style(status.kind.style()),
+            chunks[1],
+        );
+    }
+
+    if let Some(job) = &app.batch {
+        let done = job.results.len();
+        let popup = centered_rect(size, 60, 3);
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Gauge::default()
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .title(format!(" {} (Esc cancels) ", job.op.verb())),
+                )
+                .gauge_style(Style::default().fg(Color::Cyan))
+                .ratio(done as f64 / job.total.max(1) as f64)
+                .label(format!("{done} of {}", job.total)),
+            popup,
+        );
+    }
+
+    if let Some(view) = &mut app.recent {
+        let items: Vec<ListItem> = view
+            .items
+            .iter()
+            .map(|p| ListItem::new(p.display().to_string()))
+            .collect();
+        let popup = centered_rect(size, 80, popup_height(items.len(), size));
+        f.render_widget(Clear, popup);
+        f.render_stateful_widget(
+            List::new(items)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .title(" recent (Enter open, o go to, Esc close) "),
+                )
+                .highlight_symbol(&app.config.highlight_symbol)
+                .highlight_style(app.config.highlight_style),
+            popup,
+            &mut view.state,
+        );
+    }
+
+    if let Some(view) = &mut app.templates {
+        let items: Vec<ListItem> = view
+            .items
+            .iter()
+            .map(|p| {
+                let mut name = file_name_of(p);
+                if p.is_dir() {
+                    name.push('/');
+                }
+                ListItem::new(name)
+            })
+            .collect();
+        let popup = centered_rect(size, 50, popup_height(items.len(), size));
+        f.render_widget(Clear, popup);
+        f.render_stateful_widget(
+            List::new(items)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .title(" new from template (Enter pick, Esc close) "),
+                )
+                .highlight_symbol(&app.config.highlight_symbol)
+                .highlight_style(app.config.highlight_style),
+            popup,
+            &mut view.state,
+        );
+    }
+
+    if let Some(view) = &mut app.grep {
+        let cwd = &app.cwd;
+        let items: Vec<ListItem> = view
+            .hits
+            .iter()
+            .map(|hit| {
+                let name = hit.path.strip_prefix(cwd).unwrap_or(&hit.path);
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        name.display().to_string(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        format!(" ×{} ", hit.count),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled(
+                        format!("{}: ", hit.line),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::raw(hit.snippet.clone()),
+                ]))
+            })
+            .collect();
+        let popup = centered_rect(size, 90, popup_height(items.len(), size));
+        f.render_widget(Clear, popup);
+        f.render_stateful_widget(
+            List::new(items)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .title(format!(
+                            " \"{}\" in {} files (Enter open, o go to, Esc close) ",
+                            view.query,
+                            view.hits.len()
+                        )),
+                )
+                .highlight_symbol(&app.config.highlight_symbol)
+                .highlight_style(app.config.highlight_style),
+            popup,
+            &mut view.state,
+        );
+    }
+
+    if let Some(info) = &app.info {
+        let label_width = info.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+        let lines: Vec<Line> = info
+            .iter()
+            .map(|(k, v)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{k:>label_width$}  "),
+                        Style::default().fg(Color::Cyan),
+                    ),
+                    Span::raw(v.as_str()),
+                ])
+            })
+            .collect();
+        let popup = centered_rect(size, 80, popup_height(lines.len(), size));
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .title(" info (i/Esc to close) "),
+            ),
+            popup,
+        );
+    }
+}
+
+fn render_preview(f: &mut Frame, app: &mut App, area: Rect) {
+    let Some(target) = app.preview_target() else {
+        return;
+    };
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let pinned = if app.preview_pinned.is_some() {
+        " [pinned]"
+    } else {
+        ""
+    };
+    // Forget the scroll once the highlight moves on, so coming back to the
+    // file previews it from the top again
+    if app
+        .preview_scroll
+        .as_ref()
+        .is_some_and(|(p, _)| *p != target)
+    {
+        app.preview_scroll = None;
+    }
+    let offset = app.preview_offset(&target);
+    let title = if offset > 0 {
+        format!(" {name}{pinned} +{offset} ")
+    } else {
+        format!(" {name}{pinned} ")
+    };
+    let lines: Vec<Line> = match app.preview_content(&target) {
+        // The left border takes a column
+        preview::Content::Image(img) => image_lines(img, area.width.saturating_sub(1), area.height),
+        preview::Content::Lines(lines) => lines
+            .iter()
+            .skip(offset)
+            .take(area.height as usize)
+            .map(|l| Line::raw(l.as_str()))
+            .collect(),
+    };
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::LEFT)
+                .title(title)
+                .border_style(Style::default().fg(Color::DarkGray)),
+        ),
+        area,
+    );
+}
+
+/// `img` scaled to fit `width` x `height` cells, two pixels per cell: `▀`
+/// in the upper pixel's color over the lower one's. Cells are about twice
+/// as tall as wide, so this keeps the aspect ratio.
+fn image_lines(img: &image::RgbImage, width: u16, height: u16) -> Vec<Line<'static>> {
+    let (w, h) = (img.width().max(1), img.height().max(1));
+    let scale = f64::min(
+        f64::from(width) / f64::from(w),
+        f64::from(height) * 2.0 / f64::from(h),
+    );
+    let cols = ((f64::from(w) * scale) as u32).max(1);
+    let rows = ((f64::from(h) * scale) as u32).max(1);
+    let pixel = |x: u32, y: u32| {
+        let p = img.get_pixel(x * w / cols, (y * h / rows).min(h - 1));
+        Color::Rgb(p[0], p[1], p[2])
+    };
+    (0..rows.div_ceil(2))
+        .map(|row| {
+            let top = row * 2;
+            Line::from(
+                (0..cols)
+                    .map(|x| {
+                        let style = Style::default().fg(pixel(x, top));
+                        // An odd row count leaves the last cell's bottom half
+                        // empty
+                        let style = if top + 1 < rows {
+                            style.bg(pixel(x, top + 1))
+                        } else {
+                            style
+                        };
+                        Span::styled("▀", style)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect()
+}
+
+/// The places sidebar. The place matching cwd is bold; the highlight is
+/// only drawn while the sidebar has focus.
+fn render_places(f: &mut Frame, app: &mut App, area: Rect) {
+    let Some(view) = &mut app.places else {
+        return;
+    };
+    let items: Vec<ListItem> = app
+        .config
+        .places
+        .iter()
+        .map(|(name, path)| {
+            let style = if *path == app.cwd {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(name.as_str()).style(style)
+        })
+        .collect();
+    let (symbol, style) = if view.focused {
+        (
+            app.config.highlight_symbol.as_str(),
+            app.config.highlight_style,
+        )
+    } else {
+        ("", Style::default())
+    };
+    f.render_stateful_widget(
+        List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::RIGHT)
+                    .border_style(Style::default().fg(Color::DarkGray)),
+            )
+            .highlight_symbol(symbol)
+            .highlight_style(style),
+        area,
+        &mut view.state,
+    );
+}
+
+/// A prompt line: `label`, the text with a block cursor, then `hint`.
+fn render_input(f: &mut Frame, label: &str, input: &TextInput, hint: &str, area: Rect) {
+    let (before, after) = input.split_at_cursor();
+    // The cursor sits on the char after it, or a blank past the end
+    let mut rest = after.chars();
+    let under = rest.next().map_or_else(|| " ".to_string(), String::from);
+    let line = Line::from(vec![
+        Span::raw(label),
+        Span::raw(before),
+        Span::styled(under, Style::default().add_modifier(Modifier::REVERSED)),
+        Span::raw(rest.as_str()),
+        Span::styled(hint, Style::default().fg(Color::DarkGray)),
+    ]);
+    f.render_widget(Paragraph::new(line), area);
+}
+
+/// Short tags for every view setting that's off its default, e.g.
+/// `[.H][↑size][/log]`; empty when everything is at its default.
+fn active_toggles(app: &App) -> String {
+    let mut tags = Vec::new();
+    if app.peek_hidden {
+        tags.push("peek".to_string());
+    } else if app.show_pattern_hidden {
+        tags.push(".H".to_string());
+    }
+    if app.sort_mode != SortMode::Name || app.sort_reversed {
+        let arrow = if app.sort_reversed { "↑" } else { "↓" };
+        tags.push(format!("{arrow}{}", app.sort_mode.label()));
+    }
+    if !app.filter.is_empty() {
+        tags.push(format!("/{}", app.filter.value()));
+    }
+    if app.filter_case_sensitive {
+        tags.push("Aa".to_string());
+    }
+    if app.filter_is_regex {
+        tags.push("re".to_string());
+    }
+    if app.flatten {
+        tags.push("flat".to_string());
+    }
+    if app.show_full_paths {
+        tags.push("paths".to_string());
+    }
+    if app.group_marked {
+        tags.push("marked first".to_string());
+    }
+    if app.register != DEFAULT_REGISTER {
+        tags.push(format!("\"{}", app.register));
+    }
+    if app.opener == Opener::Pager {
+        tags.push("view".to_string());
+    }
+    if let Some(every) = app.auto_refresh {
+        tags.push(format!("⟳{}s", every.as_secs()));
+    }
+    tags.iter().map(|t| format!("[{t}]")).collect()
+}
+
+fn render_confirm(f: &mut Frame, confirm: &Confirm, area: Rect) {
+    let color = if confirm.action.is_destructive() {
+        Color::Red
+    } else {
+        Color::Yellow
+    };
+    f.render_widget(
+        Paragraph::new(format!("{} (y/n)", confirm.message)).style(Style::default().fg(color)),
+        area,
+    );
+}
+
+/// Height of a bordered popup with `rows` lines inside, at most all of
+/// `area`; grep results and the like can run to more rows than fit a u16.
+fn popup_height(rows: usize, area: Rect) -> u16 {
+    u16::try_from(rows)
+        .unwrap_or(u16::MAX)
+        .saturating_add(2)
+        .min(area.height)
+}
+
+/// A rect `percent_x` wide and `height` rows tall, centered in `area`.
+fn centered_rect(area: Rect, percent_x: u16, height: u16) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
 
-    let block = Block::default()
-        .borders(Borders::ALL)
-        .title(Title::from(Line::from(vec![
-            Span::raw(" "),
-            Span::styled("Ratatui File Picker", Style::default().add_modifier(Modifier::BOLD)),
-            Span::raw(" "),
-        ])))
-        .title(
-            Title::from(Line::from(vec![
-                Span::raw(" "),
-                Span::raw(format!(
-                    "cwd: {}  |  selected: {}  |  ↑/↓ move  ␣ toggle  Enter open  ⌫ up  r refresh  q quit",
-                    app.cwd.display(),
-                    app.selected_paths.len()
-                )),
-            ]))
-            .alignment(Alignment::Right),
-        )
-        .border_type(BorderType::Rounded);
+/// Below this many entries, spawning threads costs more than it saves.
+const PARALLEL_METADATA_THRESHOLD: usize = 512;
+/// The lookups are latency-bound rather than CPU-bound, so this doesn't
+/// follow the core count.
+const METADATA_THREADS: usize = 8;
 
-    let area = block.inner(size);
-    f.render_widget(block, size);
+fn read_dir_sorted(dir: &Path, probes: DirProbes) -> Result<Vec<Entry>> {
+    let dir_entries: Vec<fs::DirEntry> = fs::read_dir(dir)
+        .with_context(|| format!("reading directory {}", dir.display()))?
+        .filter_map(|res| res.ok())
+        .collect();
 
-    // Build list items
-    let items: Vec<ListItem> = app
-        .entries
-        .iter()
-        .map(|e| {
-            let mark = if app.selected_paths.contains(&e.path) {
-                "●"
-            } else {
-                "○"
-            };
-            let icon = if e.is_dir { "📁" } else { "📄" };
-            let line = Line::from(vec![
-                Span::raw(format!("{mark} {icon} ")),
-                Span::styled(
-                    &e.name,
-                    if e.is_dir {
-                        Style::default()
-                            .fg(Color::Cyan)
-                            .add_modifier(Modifier::BOLD)
-                    } else {
-                        Style::default()
-                    },
-                ),
-            ]);
-            ListItem::new(line)
+    // Each metadata() is a syscall, and a slow round trip on network
+    // filesystems, so fan the lookups out over a few threads. Order doesn't
+    // matter since we sort afterwards.
+    let mut v: Vec<Entry> = if dir_entries.len() < PARALLEL_METADATA_THRESHOLD {
+        dir_entries
+            .iter()
+            .filter_map(|e| entry_from_dir_entry(e, probes))
+            .collect()
+    } else {
+        let chunk = dir_entries.len().div_ceil(METADATA_THREADS);
+        thread::scope(|scope| {
+            let workers: Vec<_> = dir_entries
+                .chunks(chunk)
+                .map(|part| {
+                    scope.spawn(|| {
+                        part.iter()
+                            .filter_map(|e| entry_from_dir_entry(e, probes))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().unwrap_or_default())
+                .collect()
         })
-        .collect();
+    };
 
-    let list = List::new(items)
-        .highlight_symbol("➤ ")
-        .highlight_style(Style::default().bg(Color::Gray).fg(Color::Black));
+    v.sort_by(|a, b| compare_entries(a, b, SortMode::Name));
+    Ok(v)
+}
 
-    f.render_stateful_widget(list, area, &mut app.list_state);
+/// Something that can list a directory's entries.
+trait EntrySource {
+    fn read_dir(&self, dir: &Path) -> Result<Vec<Entry>>;
 }
 
-fn read_dir_sorted(dir: &Path) -> Result<Vec<Entry>> {
-    let mut v: Vec<Entry> = fs::read_dir(dir)
-        .with_context(|| format!("reading directory {}", dir.display()))?
-        .filter_map(|res| {
-            let entry = res.ok()?;
-            let md = entry.metadata().ok()?;
-            let is_dir = md.is_dir();
-            let name = entry.file_name().to_string_lossy().into_owned();
-            Some(Entry {
-                name,
-                path: entry.path(),
-                is_dir,
-            })
+/// Optional extra work on each subdirectory while listing. Either one
+/// costs an open of every subdirectory, so both are off unless asked for.
+#[derive(Clone, Copy)]
+struct DirProbes {
+    /// Find the ones we can't enter (`probe_dir_access`)
+    access: bool,
+    /// Count their entries (`U`)
+    counts: bool,
+}
+
+/// The real filesystem.
+#[derive(Clone, Copy)]
+struct FsSource {
+    probes: DirProbes,
+}
+
+impl EntrySource for FsSource {
+    fn read_dir(&self, dir: &Path) -> Result<Vec<Entry>> {
+        read_dir_sorted(dir, self.probes)
+    }
+}
+
+/// Entries whose metadata can't be read are skipped.
+fn entry_from_dir_entry(entry: &fs::DirEntry, probes: DirProbes) -> Option<Entry> {
+    // DirEntry::metadata doesn't follow symlinks; follow them ourselves so
+    // links to directories can be entered, and so we can tell broken ones
+    let link_md = entry.metadata().ok()?;
+    let (md, link) = if link_md.file_type().is_symlink() {
+        match fs::metadata(entry.path()) {
+            Ok(target) => (target, Some(LinkStatus::Valid)),
+            Err(_) => (link_md, Some(LinkStatus::Broken)),
+        }
+    } else {
+        (link_md, None)
+    };
+    let name = entry.file_name().to_string_lossy().into_owned();
+    let kind = Kind::of(md.file_type());
+    // Opening the directory asks the OS itself, which gets ACLs and root
+    // right where reading the mode bits wouldn't
+    let listing =
+        (kind == Kind::Dir && (probes.access || probes.counts)).then(|| fs::read_dir(entry.path()));
+    let (locked, child_count) = match listing {
+        Some(Ok(rd)) => (false, probes.counts.then(|| rd.count())),
+        Some(Err(_)) => (probes.access, None),
+        None => (false, None),
+    };
+    let (inode, nlinks) = inode_and_links(&md);
+    Some(Entry {
+        name,
+        path: entry.path(),
+        kind,
+        size: md.len(),
+        modified: md.modified().ok(),
+        link,
+        mode: file_mode(&md),
+        locked,
+        inode,
+        nlinks,
+        child_count,
+    })
+}
+
+/// Feed `names` to `cmd` one per line and read back the order it prints.
+fn external_order(cmd: &str, names: &[&str]) -> Result<Vec<String>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("running {cmd}"))?;
+    let input: String = names.iter().map(|n| format!("{n}\n")).collect();
+    let mut stdin = child.stdin.take().context("no stdin")?;
+    // Write from another thread so a command that streams its output can't
+    // deadlock against us on full pipe buffers
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if !output.status.success() {
+        bail!("{cmd} exited with {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// Directories first (unless `mode` mixes them in), then by `mode`, with the
+/// name as tiebreaker.
+fn compare_entries(a: &Entry, b: &Entry, mode: SortMode) -> Ordering {
+    // Names equal but for case fall back to a plain byte comparison, so
+    // `File` and `file` keep the same order on every reload
+    let by_name = || {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.name.cmp(&b.name))
+    };
+    let by_dir = if mode.groups_dirs() {
+        b.is_dir().cmp(&a.is_dir())
+    } else {
+        Ordering::Equal
+    };
+    by_dir.then_with(|| match mode {
+        SortMode::Name => by_name(),
+        SortMode::Size => b.size.cmp(&a.size).then_with(by_name),
+        SortMode::Modified | SortMode::Touched => b.modified.cmp(&a.modified).then_with(by_name),
+        // Only reached when the command failed
+        SortMode::Command => by_name(),
+    })
+}
+
+/// Label/value rows describing `path` for the info overlay. Fields the
+/// platform can't provide are left out.
+fn entry_info(path: &Path) -> Vec<(&'static str, String)> {
+    let mut rows = Vec::new();
+    let abs = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    rows.push(("path", abs.display().to_string()));
+
+    let Ok(link_md) = fs::symlink_metadata(path) else {
+        rows.push(("error", "cannot read metadata".to_string()));
+        return rows;
+    };
+    if link_md.file_type().is_symlink()
+        && let Ok(target) = fs::read_link(path)
+    {
+        rows.push(("link to", target.display().to_string()));
+    }
+    // Follow the link for the remaining fields; fall back to the link itself
+    // if it is dangling
+    let md = fs::metadata(path).unwrap_or(link_md);
+
+    rows.push(("type", Kind::of(md.file_type()).label().to_string()));
+    rows.push((
+        "size",
+        format!("{} bytes ({})", md.len(), human_size(md.len())),
+    ));
+    let times = [
+        ("created", md.created()),
+        ("modified", md.modified()),
+        ("accessed", md.accessed()),
+    ];
+    for (label, time) in times {
+        if let Ok(t) = time {
+            rows.push((label, format_time(t)));
+        }
+    }
+    rows.push(("permissions", format_permissions(&md)));
+    rows
+}
+
+fn format_time(t: SystemTime) -> String {
+    DateTime::<Local>::from(t)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// How long ago `t` was, coarsely: `42s ago`, `5m ago`, `3d ago`, ...
+fn format_age(t: SystemTime) -> String {
+    // Clock skew can put mtimes slightly in the future
+    let secs = SystemTime::now()
+        .duration_since(t)
+        .map_or(0, |d| d.as_secs());
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    const WEEK: u64 = 7 * DAY;
+    const MONTH: u64 = 30 * DAY;
+    const YEAR: u64 = 365 * DAY;
+    match secs {
+        s if s < 10 => "just now".to_string(),
+        s if s < MINUTE => format!("{s}s ago"),
+        s if s < HOUR => format!("{}m ago", s / MINUTE),
+        s if s < DAY => format!("{}h ago", s / HOUR),
+        s if s < WEEK => format!("{}d ago", s / DAY),
+        s if s < MONTH => format!("{}w ago", s / WEEK),
+        s if s < YEAR => format!("{}mo ago", s / MONTH),
+        s => format!("{}y ago", s / YEAR),
+    }
+}
+
+#[cfg(unix)]
+fn format_permissions(md: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = md.permissions().mode();
+    let mut s = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    format!("{s} ({:o})", mode & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn format_permissions(md: &fs::Metadata) -> String {
+    if md.permissions().readonly() {
+        "read-only".to_string()
+    } else {
+        "read-write".to_string()
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(md: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    md.mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(_md: &fs::Metadata) -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn inode_and_links(md: &fs::Metadata) -> (Option<u64>, Option<u64>) {
+    use std::os::unix::fs::MetadataExt;
+    (Some(md.ino()), Some(md.nlink()))
+}
+
+#[cfg(not(unix))]
+fn inode_and_links(_md: &fs::Metadata) -> (Option<u64>, Option<u64>) {
+    (None, None)
+}
+
+/// Format a byte count with binary units, e.g. `1.5 MiB`.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// `path` cut down to at most `max` columns by putting `…` in place of
+/// middle components, keeping the first one (and the root) and as many
+/// trailing ones as fit. When not even the last one fits, only the end of
+/// it is kept.
+fn shorten_path(path: &str, max: usize) -> String {
+    if path.width() <= max {
+        return path.to_string();
+    }
+    let sep = std::path::MAIN_SEPARATOR_STR;
+    let parts: Vec<&str> = path.split(sep).collect();
+    // An absolute path splits as ["", "first", ...]
+    let head_len = if parts[0].is_empty() { 2 } else { 1 }.min(parts.len());
+    let head = parts[..head_len].join(sep);
+    let mut tail = String::new();
+    for part in parts[head_len..].iter().rev() {
+        let longer = if tail.is_empty() {
+            part.to_string()
+        } else {
+            format!("{part}{sep}{tail}")
+        };
+        if head.width() + longer.width() + 3 > max {
+            break;
+        }
+        tail = longer;
+    }
+    if !tail.is_empty() {
+        return format!("{head}{sep}…{sep}{tail}");
+    }
+    let mut end = Vec::new();
+    let mut width = 1;
+    for c in path.chars().rev() {
+        width += c.width().unwrap_or(0);
+        if width > max {
+            break;
+        }
+        end.push(c);
+    }
+    std::iter::once('…').chain(end.into_iter().rev()).collect()
+}
+
+/// Parse a size like `512`, `1.5k`, `100M` or `2GiB`; units are powers
+/// of 1024, matching `human_size`.
+fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let unit = unit.trim().to_ascii_lowercase();
+    let unit = unit
+        .strip_suffix("ib")
+        .or_else(|| unit.strip_suffix('b'))
+        .unwrap_or(&unit);
+    let power = match unit {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        "p" => 5,
+        _ => return None,
+    };
+    Some((number * 1024f64.powi(power)) as u64)
+}
+
+/// Shell-style glob match of a whole name: `*` matches any run of
+/// characters, `?` matches exactly one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    // Position of the last `*` and the name index it is currently absorbing up to
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            // Let the last `*` swallow one more character and retry
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Byte range of the first case-insensitive occurrence of `needle`.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<(usize, usize)> {
+    let needle: Vec<char> = needle.chars().collect();
+    if needle.is_empty() {
+        return None;
+    }
+    let chars: Vec<(usize, char)> = haystack.char_indices().collect();
+    chars
+        .windows(needle.len())
+        .find(|w| {
+            w.iter()
+                .zip(&needle)
+                .all(|((_, a), b)| a.to_lowercase().eq(b.to_lowercase()))
         })
-        .collect();
+        .map(|w| {
+            let (last, c) = w[w.len() - 1];
+            (w[0].0, last + c.len_utf8())
+        })
+}
 
-    v.sort_by(|a, b| match (a.is_dir, b.is_dir) {
-        (true, false) => Ordering::Less,
-        (false, true) => Ordering::Greater,
-        _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-    });
-    Ok(v)
+/// Where in `name` the active search matches, or failing that the filter,
+/// so matches stay picked out while navigating normally too.
+fn query_match(app: &App, name: &str) -> Option<(usize, usize)> {
+    let filter = app.filter.value();
+    if !app.search_query.is_empty() {
+        find_ignore_case(name, app.search_query.value())
+    } else if app.filter_is_regex {
+        let found = app.filter_regex.as_ref()?.find(name)?;
+        Some((found.start(), found.end()))
+    } else if app.filter_case_sensitive && !filter.is_empty() {
+        name.find(filter).map(|start| (start, start + filter.len()))
+    } else {
+        find_ignore_case(name, filter)
+    }
+}
+
+/// `name` as spans in `style`, with the byte range `found` picked out.
+fn highlight_match(name: &str, found: Option<(usize, usize)>, style: Style) -> Vec<Span<'_>> {
+    match found {
+        Some((start, end)) => vec![
+            Span::styled(&name[..start], style),
+            Span::styled(&name[start..end], style.bg(Color::Yellow).fg(Color::Black)),
+            Span::styled(&name[end..], style),
+        ],
+        None => vec![Span::styled(name, style)],
+    }
+}
+
+/// The text the listing shows for `e`: its name, or its full path when
+/// those are on. A path that isn't UTF-8 falls back to the (lossy) name.
+fn shown_name<'a>(app: &App, e: &'a Entry) -> &'a str {
+    match e.path.to_str() {
+        Some(path) if app.show_full_paths => path,
+        _ => e.name.as_str(),
+    }
+}
+
+/// The child count shown after a directory's name, when counts are on.
+fn count_badge(app: &App, e: &Entry) -> String {
+    match e.child_count {
+        _ if !e.is_dir() || !app.fs_source.probes.counts || app.archive.is_some() => String::new(),
+        Some(n) => format!(" ({n})"),
+        None => " (?)".to_string(),
+    }
+}
+
+/// Type suffix and child count together, for measuring a name's cell.
+fn name_extras(app: &App, e: &Entry) -> String {
+    let suffix = if app.show_type_suffix {
+        type_suffix(e)
+    } else {
+        ""
+    };
+    format!("{suffix}{}", count_badge(app, e))
+}
+
+/// Keep as many of the right-hand columns `widths` as fit beside names
+/// `name_width` wide in `available` columns, dropping them in order;
+/// dropped columns come back as 0. Columns are kept one space from the
+/// names and two from each other.
+fn fit_columns<const N: usize>(
+    available: usize,
+    name_width: usize,
+    mut widths: [usize; N],
+) -> [usize; N] {
+    for i in 0..N {
+        let shown = widths.iter().filter(|&&w| w > 0).count();
+        let gaps = if shown == 0 { 0 } else { 2 * shown - 1 };
+        if name_width + widths.iter().sum::<usize>() + gaps <= available {
+            break;
+        }
+        widths[i] = 0;
+    }
+    widths
+}
+
+/// `ls -F`-style marker appended to the name: `/` directory, `@` symlink,
+/// `|` FIFO, `=` socket, `*` executable.
+fn type_suffix(e: &Entry) -> &'static str {
+    if e.link.is_some() {
+        return "@";
+    }
+    match e.kind {
+        Kind::Dir => "/",
+        Kind::Fifo => "|",
+        Kind::Socket => "=",
+        _ if e.is_executable() => "*",
+        _ => "",
+    }
+}
+
+/// `path` resolved against `base` without touching the disk: `.` and
+/// repeated or trailing slashes drop out, and `..` removes the component
+/// before it (never climbing past the root), the way a shell's `cd` reads
+/// it. Unlike canonicalize this works for paths that don't exist.
+fn normalize_path(base: &Path, path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Last path component for messages, lossily converted.
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Split a file name into its base name and extension (without the dot).
+/// Only the last path component counts, so the dot in `v1.2/README` isn't
+/// an extension. Dotfiles such as `.bashrc` and names without a dot have
+/// no extension.
+fn split_extension(name: &str) -> (&str, &str) {
+    let start = name.rfind('/').map_or(0, |i| i + 1);
+    match name[start..].rfind('.') {
+        Some(i) if i > 0 && start + i + 1 < name.len() => {
+            (&name[..start + i], &name[start + i + 1..])
+        }
+        _ => (name, ""),
+    }
 }
 
-fn open_with_editor(path: &Path) -> Result<()> {
+/// What gets launched for a file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Opener {
+    /// `$EDITOR`, for editing
+    Editor,
+    /// `$PAGER`, for read-only viewing
+    Pager,
+    /// `$DIFFTOOL`, comparing two files
+    Diff,
+}
+
+impl Opener {
+    fn env_var(self) -> &'static str {
+        match self {
+            Opener::Editor => "EDITOR",
+            Opener::Pager => "PAGER",
+            Opener::Diff => "DIFFTOOL",
+        }
+    }
+
+    /// Used when the env var is unset. `less -F` quits by itself when the
+    /// file fits on one screen; `-X` keeps its output from being cleared.
+    fn default_program(self) -> Option<&'static str> {
+        match self {
+            Opener::Editor => None,
+            Opener::Pager => Some("less -RFX"),
+            Opener::Diff => Some("diff -u"),
+        }
+    }
+}
+
+/// The platform's "open with the default application" command
+#[cfg(target_os = "macos")]
+const SYSTEM_OPENER: &[&str] = &["open"];
+#[cfg(windows)]
+const SYSTEM_OPENER: &[&str] = &["cmd", "/C", "start", ""];
+#[cfg(not(any(target_os = "macos", windows)))]
+const SYSTEM_OPENER: &[&str] = &["xdg-open"];
+
+/// Launch `path` in the default application without waiting for it, since
+/// that's usually a GUI program with a life of its own. Only failing to
+/// start the opener is reported; a thread reaps it once it exits.
+fn open_detached(path: &Path) -> io::Result<&'static str> {
+    let mut child = Command::new(SYSTEM_OPENER[0])
+        .args(&SYSTEM_OPENER[1..])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                io::Error::new(e.kind(), format!("{} is not installed", SYSTEM_OPENER[0]))
+            }
+            _ => e,
+        })?;
+    thread::spawn(move || child.wait());
+    Ok(SYSTEM_OPENER[0])
+}
+
+/// Open `path` with `$EDITOR` or `$PAGER`, passing `args` (e.g. `-R`)
+/// before the path. With `at`, a single path is opened at that line, in
+/// whatever syntax the program wants (see `position_args`).
+///
+/// If the variable is unset or its program can't be spawned, each of
+/// `fallbacks` is tried in order. That list comes from the
+/// `fallback_openers` config key and defaults to `less`, then `vi`.
+fn open_with(
+    opener: Opener,
+    paths: &[PathBuf],
+    args: &[String],
+    at: Option<Position>,
+    fallbacks: &[String],
+) -> Result<()> {
     // Leave raw/alt to let the editor take over
     // We'll temporarily tear down the TUI, spawn, then rebuild automatically
     // by re-entering alt-screen on redraw.
+    let mut out = term::output()?;
     disable_raw_mode().ok();
     // We intentionally do not LeaveAlternateScreen here, because some editors
     // handle alt-screen themselves poorly. Use a small trick: print a reset.
     // But a safer cross-terminal approach is to fully leave alt-screen:
-    let mut stdout = io::stdout();
-    let _ = execute!(stdout, LeaveAlternateScreen);
-
-    // Choose editor
-    let editor = env::var("EDITOR").unwrap_or_else(|_| "less".to_string());
-    let cmdline = format!(
-        "{} {}",
-        editor,
-        shell_escape::escape(path.to_string_lossy().into_owned().into())
-    );
+    let _ = term::leave_alt_screen(&mut out);
 
-    // If EDITOR has spaces/flags, run via sh -c
-    let status = if editor.contains(' ') {
-        Command::new("sh").arg("-c").arg(&cmdline).status()
-    } else {
-        Command::new(editor).arg(path).status()
+    let env_program = env::var(opener.env_var()).ok();
+    let uses_default = env_program.is_none();
+    // The paths, or the path plus its position as `program` spells it
+    let targets = |program: &str| -> Vec<OsString> {
+        match (at, paths) {
+            (Some(at), [path]) => position_args(program, path, at),
+            _ => paths.iter().map(|p| p.clone().into_os_string()).collect(),
+        }
+    };
+    let program = env_program.or_else(|| opener.default_program().map(String::from));
+    let (status, tried) = run_first(opener, program, args, &targets, fallbacks);
+
+    // Our default pager exits immediately on short files, and plain diff
+    // always does; hold the output until the user has seen it instead of
+    // snapping back to the TUI
+    if uses_default
+        && status.is_ok()
+        && match (opener, paths) {
+            (Opener::Pager, [path]) => fits_on_screen(path),
+            (Opener::Diff, _) => true,
+            _ => false,
+        }
+    {
+        let _ = write!(out, "-- press Enter to return --");
+        let _ = out.flush();
+        let _ = io::stdin().read_line(&mut String::new());
     }
-    .or_else(|_| Command::new("less").arg(path).status())
-    .or_else(|_| Command::new("vi").arg(path).status())?;
 
     // Return to TUI
-    let _ = execute!(io::stdout(), EnterAlternateScreen);
+    let _ = term::enter_alt_screen(&mut out);
+    let _ = term::enable_paste(&mut out);
     enable_raw_mode().ok();
 
-    if !status.success() {
-        eprintln!("Editor exited with status: {:?}", status.code());
+    open_verdict(opener, status, &tried, uses_default)
+}
+
+/// Run `program` (through `sh -c` when it has spaces or flags), then each
+/// of `fallbacks` until one can be started; `targets` spells the paths for
+/// each. Returns how the last attempt went and every program tried.
+fn run_first(
+    opener: Opener,
+    program: Option<String>,
+    args: &[String],
+    targets: &dyn Fn(&str) -> Vec<OsString>,
+    fallbacks: &[String],
+) -> (io::Result<ExitStatus>, Vec<String>) {
+    let mut status = Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("${} is not set", opener.env_var()),
+    ));
+    let mut tried = Vec::new();
+    if let Some(program) = program {
+        let targets = targets(&program);
+        let mut cmdline = program.clone();
+        for arg in args {
+            cmdline.push(' ');
+            cmdline.push_str(&shell_escape::escape(arg.clone()));
+        }
+        for target in &targets {
+            cmdline.push(' ');
+            cmdline.push_str(&shell_escape::escape(target.to_string_lossy().into_owned()));
+        }
+
+        // If the program has spaces/flags, run via sh -c
+        status = if program.contains(' ') {
+            Command::new("sh")
+                .arg("-c")
+                .arg(&cmdline)
+                .stdout(term::child_stdout())
+                .status()
+                .and_then(|s| {
+                    // The shell's "command not found", so the fallbacks
+                    // still get their turn
+                    if s.code() == Some(127) {
+                        Err(io::Error::from(io::ErrorKind::NotFound))
+                    } else {
+                        Ok(s)
+                    }
+                })
+        } else {
+            Command::new(&program)
+                .args(args)
+                .args(&targets)
+                .stdout(term::child_stdout())
+                .status()
+        };
+        tried.push(program);
+    }
+    // Extra args are program-specific, so the fallbacks don't get them;
+    // the position is spelled for each one, so they do get that
+    for fallback in fallbacks {
+        if status.is_ok() {
+            break;
+        }
+        status = Command::new(fallback)
+            .args(targets(fallback))
+            .stdout(term::child_stdout())
+            .status();
+        tried.push(fallback.clone());
+    }
+    (status, tried)
+}
+
+/// What `open_with` makes of how running the opener went: success, a bad
+/// exit status, or nothing that could be started at all.
+fn open_verdict(
+    opener: Opener,
+    status: io::Result<ExitStatus>,
+    tried: &[String],
+    uses_default: bool,
+) -> Result<()> {
+    match status {
+        Ok(s) if s.success() => Ok(()),
+        // How diff tools say the files differ
+        Ok(s) if opener == Opener::Diff && s.code() == Some(1) => Ok(()),
+        Ok(s) => bail!(
+            "{} exited with status {}",
+            tried.last().map_or("editor", String::as_str),
+            s.code().map_or("unknown".to_string(), |c| c.to_string())
+        ),
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !tried.is_empty() => {
+            let unset = if uses_default {
+                format!("${} is not set; ", opener.env_var())
+            } else {
+                String::new()
+            };
+            bail!("nothing to open with: {unset}tried {}", tried.join(", "))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// How `program` (the first word of it, anyway) is told to open `path` at
+/// `at`. vi and friends take `+LINE` and nothing for the column; most other
+/// terminal editors, emacs and micro among them, take `+LINE:COL`. A few
+/// GUI editors want the position glued onto the path instead.
+fn position_args(program: &str, path: &Path, at: Position) -> Vec<OsString> {
+    let name = program
+        .split_whitespace()
+        .next()
+        .and_then(|p| Path::new(p).file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (line, column) = (at.line, at.column.unwrap_or(1));
+    let suffixed = || {
+        let mut arg = path.as_os_str().to_owned();
+        arg.push(format!(":{line}:{column}"));
+        arg
+    };
+    let plus = |arg: String| vec![OsString::from(arg), path.into()];
+    match name.as_str() {
+        "vi" | "vim" | "nvim" | "view" | "gvim" => plus(format!("+{line}")),
+        "less" => plus(format!("+{line}g")),
+        "nano" => plus(format!("+{line},{column}")),
+        "code" | "codium" => vec!["--goto".into(), suffixed()],
+        "hx" | "helix" | "subl" | "zed" => vec![suffixed()],
+        _ => plus(format!("+{line}:{column}")),
     }
-    Ok(())
+}
+
+/// Whether `path` would fit on one terminal screen, i.e. whether `less -F`
+/// will quit without waiting.
+fn fits_on_screen(path: &Path) -> bool {
+    let Ok((cols, rows)) = crossterm::terminal::size() else {
+        return false;
+    };
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut lines = 0;
+    for line in io::BufRead::lines(io::BufReader::new(file)) {
+        let Ok(line) = line else {
+            // Non-UTF-8; less shows it paged either way
+            return false;
+        };
+        lines += 1 + line.width() / cols.max(1) as usize;
+        if lines >= rows as usize {
+            return false;
+        }
+    }
+    true
 }
 
 // Minimal shell-escape for safety in `sh -c` case.
@@ -315,3 +4705,514 @@ mod shell_escape {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory for one test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sfp-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn app_in(dir: &Path) -> App {
+        App::new(dir.to_path_buf(), Config::default(), &Args::default()).unwrap()
+    }
+
+    fn file_entry(name: &str) -> Entry {
+        Entry {
+            name: name.to_string(),
+            path: PathBuf::from("/nowhere").join(name),
+            kind: Kind::File,
+            size: 0,
+            modified: None,
+            link: None,
+            mode: 0,
+            locked: false,
+            inode: None,
+            nlinks: None,
+            child_count: None,
+        }
+    }
+
+    fn confirm(app: &mut App, action: ConfirmAction) {
+        app.confirm = Some(Confirm {
+            message: "sure?".to_string(),
+            action,
+        });
+    }
+
+    fn wait_for_batch(app: &mut App) {
+        while let Some(job) = &mut app.batch {
+            job.poll();
+            if job.finished {
+                app.batch = None;
+            } else {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+
+    #[test]
+    fn confirmed_delete_runs() {
+        let dir = scratch_dir("confirm-delete-yes");
+        let file = dir.join("doomed");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        confirm(&mut app, ConfirmAction::Delete(vec![file.clone()]));
+        app.resolve_confirm(true).unwrap();
+        assert!(app.confirm.is_none());
+        wait_for_batch(&mut app);
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn declined_delete_does_nothing() {
+        let dir = scratch_dir("confirm-delete-no");
+        let file = dir.join("kept");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        confirm(&mut app, ConfirmAction::Delete(vec![file.clone()]));
+        app.resolve_confirm(false).unwrap();
+        assert!(app.confirm.is_none());
+        assert!(app.batch.is_none());
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn export_runs_only_when_confirmed() {
+        let dir = scratch_dir("confirm-export");
+        let dest = dir.join("listing.txt");
+        let mut app = app_in(&dir);
+        confirm(&mut app, ConfirmAction::Export(dest.clone()));
+        app.resolve_confirm(false).unwrap();
+        assert!(app.confirm.is_none());
+        assert!(!dest.exists());
+
+        confirm(&mut app, ConfirmAction::Export(dest.clone()));
+        app.resolve_confirm(true).unwrap();
+        assert!(app.confirm.is_none());
+        assert!(dest.exists());
+    }
+
+    #[test]
+    fn open_runs_only_when_confirmed() {
+        let dir = scratch_dir("confirm-open");
+        let file = dir.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        let req = || OpenRequest::single(file.clone(), Opener::Editor);
+        confirm(&mut app, ConfirmAction::Open(req()));
+        app.resolve_confirm(false).unwrap();
+        assert!(app.confirm.is_none());
+        assert!(app.launched.is_empty());
+
+        confirm(&mut app, ConfirmAction::Open(req()));
+        app.resolve_confirm(true).unwrap();
+        assert!(app.confirm.is_none());
+        assert_eq!(app.launched, vec![vec![file.clone()]]);
+    }
+
+    #[test]
+    fn places_keys_survive_an_emptied_list() {
+        let dir = scratch_dir("places-empty");
+        let mut app = app_in(&dir);
+        app.config.places = vec![("Here".to_string(), dir.clone())];
+        app.toggle_places();
+        app.config.places.clear();
+        let j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE);
+        app.handle_places_key(j).unwrap();
+        assert!(app.places.is_none());
+    }
+
+    #[test]
+    fn failed_auto_refresh_keeps_running() {
+        let dir = scratch_dir("auto-refresh");
+        let root = fs::canonicalize(&dir).unwrap().join("watched");
+        fs::create_dir(&root).unwrap();
+        // With cwd at --root, there's no ancestor to fall back to
+        let args = Args {
+            root: Some(root.clone()),
+            ..Args::default()
+        };
+        let mut app = App::new(root.clone(), Config::default(), &args).unwrap();
+        app.auto_refresh = Some(Duration::ZERO);
+        fs::remove_dir(&root).unwrap();
+        app.poll_auto_refresh();
+        assert!(app.status.is_some_and(|s| s.kind == MessageKind::Error));
+    }
+
+    #[test]
+    fn names_differing_only_by_case_sort_the_same_every_time() {
+        let orders = [
+            ["b", "B", "a", "A"],
+            ["A", "a", "B", "b"],
+            ["a", "b", "A", "B"],
+        ];
+        for names in orders {
+            for mode in [SortMode::Name, SortMode::Size, SortMode::Modified] {
+                let mut entries: Vec<Entry> = names.iter().map(|n| file_entry(n)).collect();
+                entries.sort_by(|a, b| compare_entries(a, b, mode));
+                let sorted: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+                assert_eq!(sorted, ["A", "a", "B", "b"]);
+            }
+        }
+    }
+
+    #[test]
+    fn moved_file_stays_marked_under_its_new_path() {
+        let dir = scratch_dir("carry-mark");
+        let dest = dir.join("dest");
+        fs::create_dir(&dest).unwrap();
+        let file = dir.join("report.txt");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        let e = app.entries.iter().find(|e| e.path == file).unwrap().clone();
+        app.set_marked(&e, true);
+        app.batch = Some(batch::Job::spawn(
+            batch::Op::Move,
+            vec![file.clone()],
+            dest.clone(),
+        ));
+        while app.batch.is_some() {
+            app.poll_batch().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let moved = dest.join("report.txt");
+        assert!(moved.exists());
+        assert!(app.selected_paths.contains(&moved));
+        assert!(!app.selected_paths.contains(&file));
+        assert_eq!(app.marked_sizes.get(&moved), Some(&1));
+    }
+
+    #[test]
+    fn go_to_paths_resolve_like_cd() {
+        let base = Path::new("/srv/work");
+        let cases = [
+            (".", "/srv/work"),
+            ("..", "/srv"),
+            ("../..", "/"),
+            ("../../../..", "/"),
+            ("sub/", "/srv/work/sub"),
+            ("sub//deeper/", "/srv/work/sub/deeper"),
+            ("./sub/../other", "/srv/work/other"),
+            ("sub", "/srv/work/sub"),
+            ("/etc", "/etc"),
+            ("/etc/../usr/./lib/", "/usr/lib"),
+        ];
+        for (input, expected) in cases {
+            let got = normalize_path(base, &config::expand_home(input));
+            assert_eq!(got, Path::new(expected), "{input}");
+        }
+        if let Some(home) = env::var_os("HOME") {
+            let home = normalize_path(Path::new("/"), Path::new(&home));
+            for (input, rest) in [("~", ""), ("~/", ""), ("~/notes/..", ""), ("~/a/b", "a/b")] {
+                let got = normalize_path(base, &config::expand_home(input));
+                assert_eq!(got, home.join(rest), "{input}");
+            }
+        }
+    }
+
+    #[test]
+    fn grouping_marked_keeps_each_sort_mode_within_groups() {
+        let dir = scratch_dir("group-marked");
+        let mut app = app_in(&dir);
+        let names = ["delta", "Alpha", "charlie", "bravo", "echo", "foxtrot"];
+        app.all_entries = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let mut e = file_entry(name);
+                if i % 3 == 0 {
+                    e.kind = Kind::Dir;
+                }
+                e.size = (i as u64 * 37) % 11;
+                e.modified =
+                    Some(SystemTime::UNIX_EPOCH + Duration::from_secs((i as u64 * 53) % 17));
+                e
+            })
+            .collect();
+        for name in ["charlie", "delta", "foxtrot"] {
+            app.selected_paths
+                .insert(PathBuf::from("/nowhere").join(name));
+        }
+        let order =
+            |app: &App| -> Vec<PathBuf> { app.entries.iter().map(|e| e.path.clone()).collect() };
+        let mut mode = SortMode::Name;
+        loop {
+            app.sort_mode = mode;
+            app.group_marked = false;
+            app.rebuild_view();
+            let plain = order(&app);
+            app.group_marked = true;
+            app.rebuild_view();
+            let (marked, unmarked): (Vec<PathBuf>, Vec<PathBuf>) = plain
+                .into_iter()
+                .partition(|p| app.selected_paths.contains(p));
+            assert_eq!(order(&app), [marked, unmarked].concat(), "{}", mode.label());
+            mode = mode.next();
+            if mode == SortMode::Name {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn wide_names_keep_the_columns_aligned() {
+        assert_eq!("日本語".width(), 6);
+        assert_eq!("😀".width(), 2);
+        let dir = scratch_dir("wide-names");
+        for name in ["日本語.txt", "😀.md", "plain.rs"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let mut app = app_in(&dir);
+        app.show_ext_column = true;
+        app.time_column = TimeColumn::Hidden;
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(60, 8)).unwrap();
+        terminal.draw(|f| ui(f, &mut app)).unwrap();
+        let buf = terminal.backend().buffer();
+        // Where each row's last visible character lands, by the name's
+        // first one
+        let row_end = |first: &str| {
+            (0..buf.area.height)
+                .find(|&y| (0..buf.area.width).any(|x| buf.get(x, y).symbol() == first))
+                .map(|y| {
+                    let x = (1..buf.area.width - 1)
+                        .rev()
+                        .find(|&x| buf.get(x, y).symbol() != " ")
+                        .unwrap();
+                    (x, buf.get(x, y).symbol().to_string())
+                })
+                .unwrap()
+        };
+        let (cjk, cjk_last) = row_end("日");
+        let (emoji, emoji_last) = row_end("😀");
+        let (ascii, ascii_last) = row_end("p");
+        assert_eq!(
+            (cjk_last.as_str(), emoji_last.as_str(), ascii_last.as_str()),
+            ("t", "d", "s")
+        );
+        assert_eq!(cjk, ascii);
+        assert_eq!(emoji, ascii);
+    }
+
+    #[test]
+    fn opening_with_nothing_installed_is_an_error() {
+        let targets = |_: &str| vec![OsString::from("/nowhere/file")];
+        let fallbacks = ["sfp-no-such-pager".to_string()];
+        for program in ["sfp-no-such-editor", "sfp-no-such-editor --wait"] {
+            let (status, tried) = run_first(
+                Opener::Editor,
+                Some(program.to_string()),
+                &[],
+                &targets,
+                &fallbacks,
+            );
+            assert_eq!(tried, [program, "sfp-no-such-pager"]);
+            let err = open_verdict(Opener::Editor, status, &tried, false).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("nothing to open with: tried {program}, sfp-no-such-pager")
+            );
+        }
+        // Unset, with no default and no fallbacks, nothing is run at all
+        let (status, tried) = run_first(Opener::Editor, None, &[], &targets, &[]);
+        assert!(tried.is_empty());
+        let err = open_verdict(Opener::Editor, status, &tried, true).unwrap_err();
+        assert_eq!(err.to_string(), "$EDITOR is not set");
+    }
+
+    #[test]
+    fn revealing_a_flattened_file_leaves_flatten() {
+        let dir = scratch_dir("reveal-flat");
+        let sub = dir.join("sub");
+        fs::create_dir(&sub).unwrap();
+        let file = sub.join("deep.txt");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        app.toggle_flatten();
+        while app.flatten_job.is_some() {
+            app.poll_flatten();
+            thread::sleep(Duration::from_millis(5));
+        }
+        app.select_path(&file);
+        app.reveal().unwrap();
+        assert!(!app.flatten);
+        assert!(app.flatten_job.is_none());
+        assert_eq!(app.cwd, sub);
+        assert_eq!(app.selected_entry().map(|e| &e.path), Some(&file));
+    }
+
+    #[test]
+    fn ext_column_with_full_paths_under_a_dotted_directory() {
+        let dir = scratch_dir("project.version2");
+        for name in ["Makefile", "a.c"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let mut app = app_in(&dir);
+        app.show_ext_column = true;
+        app.show_full_paths = true;
+        app.time_column = TimeColumn::Hidden;
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(120, 8)).unwrap();
+        terminal.draw(|f| ui(f, &mut app)).unwrap();
+    }
+
+    #[test]
+    fn extensions_come_from_the_last_component_only() {
+        let cases = [
+            ("main.rs", ("main", "rs")),
+            (".bashrc", (".bashrc", "")),
+            ("Makefile", ("Makefile", "")),
+            ("v1.2/README", ("v1.2/README", "")),
+            ("v1.2/.env", ("v1.2/.env", "")),
+            ("v1.2/notes.txt", ("v1.2/notes", "txt")),
+            (
+                "/srv/project.version2/a.c",
+                ("/srv/project.version2/a", "c"),
+            ),
+            ("a.b/c.", ("a.b/c.", "")),
+        ];
+        for (name, want) in cases {
+            assert_eq!(split_extension(name), want, "{name}");
+        }
+    }
+
+    #[test]
+    fn undo_reverses_the_last_move_or_copy() {
+        let dir = scratch_dir("undo");
+        let dest = dir.join("dest");
+        fs::create_dir(&dest).unwrap();
+        let file = dir.join("notes.txt");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        let e = app.entries.iter().find(|e| e.path == file).unwrap().clone();
+        app.set_marked(&e, true);
+        app.batch = Some(batch::Job::spawn(
+            batch::Op::Move,
+            vec![file.clone()],
+            dest.clone(),
+        ));
+        while app.batch.is_some() {
+            app.poll_batch().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let moved = dest.join("notes.txt");
+        assert!(moved.exists());
+        app.undo().unwrap();
+        assert!(file.exists());
+        assert!(!moved.exists());
+        assert!(app.selected_paths.contains(&file));
+
+        let copy = dir.join("copy.txt");
+        app.duplicate(&file, "copy.txt").unwrap();
+        assert!(copy.exists());
+        app.undo().unwrap();
+        assert!(!copy.exists());
+        assert!(file.exists());
+
+        app.undo().unwrap();
+        assert_eq!(
+            app.status.as_ref().map(|s| s.text.as_str()),
+            Some("nothing to undo")
+        );
+    }
+
+    #[test]
+    fn undo_after_a_delete_says_it_was_permanent() {
+        let dir = scratch_dir("undo-delete");
+        let file = dir.join("gone.txt");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        app.batch = Some(batch::Job::spawn(
+            batch::Op::Delete,
+            vec![file.clone()],
+            PathBuf::new(),
+        ));
+        while app.batch.is_some() {
+            app.poll_batch().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(!file.exists());
+        app.undo().unwrap();
+        assert!(!file.exists());
+        let status = app.status.as_ref().unwrap();
+        assert!(status.text.contains("permanent"), "{}", status.text);
+    }
+
+    #[test]
+    fn revealing_a_directory_enters_it_even_when_picking_dirs() {
+        let dir = scratch_dir("reveal-pick-dirs");
+        let sub = dir.join("sub");
+        fs::create_dir(&sub).unwrap();
+        let mut app = app_in(&dir);
+        app.pick_dirs = true;
+        app.select_path(&sub);
+        app.reveal().unwrap();
+        assert_eq!(app.cwd, sub);
+        assert!(app.selected_paths.is_empty());
+        assert!(app.picked.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refused_archive_leaves_the_listing_on_disk() {
+        let outside = scratch_dir("archive-outside");
+        let tarball = outside.join("a.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&tarball).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "inner.txt", &b"x"[..])
+            .unwrap();
+        builder.finish().unwrap();
+        let dir = scratch_dir("archive-root");
+        let link = dir.join("link.tar");
+        std::os::unix::fs::symlink(&tarball, &link).unwrap();
+        let mut app = app_in(&dir);
+        app.root = Some(fs::canonicalize(&dir).unwrap());
+        app.select_path(&link);
+        app.enter().unwrap();
+        assert!(app.archive.is_none());
+        assert_eq!(app.cwd, dir);
+        app.reload_entries().unwrap();
+        assert!(app.entries.iter().any(|e| e.path == link));
+    }
+
+    #[test]
+    fn huge_popups_are_clamped_to_the_screen() {
+        let area = Rect::new(0, 0, 80, 24);
+        assert_eq!(popup_height(3, area), 5);
+        assert_eq!(popup_height(65_534, area), 24);
+        assert_eq!(popup_height(70_000, area), 24);
+    }
+
+    #[test]
+    fn diffing_a_marked_directory_names_it() {
+        let dir = scratch_dir("diff-dir");
+        let sub = dir.join("sub");
+        fs::create_dir(&sub).unwrap();
+        let file = dir.join("a.txt");
+        fs::write(&file, "x").unwrap();
+        let mut app = app_in(&dir);
+        for path in [&sub, &file] {
+            let e = app
+                .entries
+                .iter()
+                .find(|e| &e.path == path)
+                .unwrap()
+                .clone();
+            app.set_marked(&e, true);
+        }
+        app.diff_marked().unwrap();
+        assert!(app.launched.is_empty());
+        assert_eq!(
+            app.status.as_ref().map(|s| s.text.as_str()),
+            Some("cannot diff a directory: sub")
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Content for the preview pane: the head of a text file, a thumbnail of
+//! an image or the names in a directory. Reads are capped so previewing a
+//! huge file stays cheap.
+
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+use image::RgbImage;
+
+/// Never read more than this much of a file for previewing
+const MAX_BYTES: u64 = 64 * 1024;
+
+/// How much of the start of a file `looks_binary` checks
+const BINARY_HEAD_BYTES: usize = MAX_BYTES as usize;
+
+/// Images bigger than this aren't decoded; they need the whole file
+const MAX_IMAGE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Thumbnails are shrunk to fit this many pixels a side, plenty for a pane
+/// drawn at two pixels per cell
+const THUMBNAIL_PIXELS: u32 = 256;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "webp"];
+
+/// Directories list at most this many names
+const MAX_DIR_ENTRIES: usize = 200;
+
+pub enum Content {
+    Lines(Vec<String>),
+    Image(RgbImage),
+}
+
+impl Content {
+    /// The text lines; none for an image.
+    pub fn lines(&self) -> &[String] {
+        match self {
+            Content::Lines(lines) => lines,
+            Content::Image(_) => &[],
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Content {
+    if path.is_dir() {
+        return Content::Lines(load_dir(path));
+    }
+    // Reading a FIFO would block, and devices may never end
+    match fs::metadata(path) {
+        Ok(md) if !md.is_file() => return note("<not a regular file>"),
+        Ok(md) if is_image(path) => {
+            if md.len() > MAX_IMAGE_BYTES {
+                return note("<image too big to preview>");
+            }
+            return match image::open(path) {
+                Ok(img) => Content::Image(
+                    img.thumbnail(THUMBNAIL_PIXELS, THUMBNAIL_PIXELS)
+                        .into_rgb8(),
+                ),
+                Err(e) => Content::Lines(vec![format!("<cannot decode image: {e}>")]),
+            };
+        }
+        _ => {}
+    }
+    Content::Lines(load_text(path))
+}
+
+fn note(text: &str) -> Content {
+    Content::Lines(vec![text.to_string()])
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn load_text(path: &Path) -> Vec<String> {
+    let mut buf = Vec::new();
+    let read = File::open(path).and_then(|f| f.take(MAX_BYTES).read_to_end(&mut buf));
+    if let Err(e) = read {
+        return vec![format!("<cannot read: {e}>")];
+    }
+    if looks_binary(&buf) {
+        return vec!["<binary file>".to_string()];
+    }
+    String::from_utf8_lossy(&buf)
+        .lines()
+        .map(|l| l.replace('\t', "    "))
+        .collect()
+}
+
+/// Whether `data` is binary rather than text. A NUL byte in the head is a
+/// good enough signal; the preview, grep and copying contents all go by it.
+pub fn looks_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_HEAD_BYTES)].contains(&0)
+}
+
+fn load_dir(path: &Path) -> Vec<String> {
+    let Ok(rd) = fs::read_dir(path) else {
+        return vec!["<cannot read directory>".to_string()];
+    };
+    let mut names: Vec<String> = rd
+        .filter_map(|res| {
+            let entry = res.ok()?;
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().ok()?.is_dir() {
+                name.push('/');
+            }
+            Some(name)
+        })
+        .take(MAX_DIR_ENTRIES)
+        .collect();
+    names.sort_by_key(|n| n.to_lowercase());
+    if names.is_empty() {
+        names.push("<empty directory>".to_string());
+    }
+    names
+}
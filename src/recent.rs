@@ -0,0 +1,47 @@
+//! Recently opened files, persisted one path per line (most recent first)
+//! in `$XDG_STATE_HOME/simple-file-picker/recent` (or `~/.local/state/...`).
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Oldest entries fall off the end past this many
+const MAX_RECENT: usize = 50;
+
+fn state_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))?;
+    Some(base.join("simple-file-picker").join("recent"))
+}
+
+/// Recent files that still exist, most recent first.
+pub fn load() -> Vec<PathBuf> {
+    let Some(text) = state_path().and_then(|p| fs::read_to_string(p).ok()) else {
+        return Vec::new();
+    };
+    text.lines()
+        .map(PathBuf::from)
+        .filter(|p| p.exists())
+        .collect()
+}
+
+/// Move `path` to the front of the list. Failures are ignored; the recent
+/// list is a convenience and must never get in the way of opening a file.
+pub fn record(path: &Path) {
+    let Some(state) = state_path() else {
+        return;
+    };
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut list = load();
+    list.retain(|p| p != &path);
+    list.insert(0, path);
+    list.truncate(MAX_RECENT);
+
+    let text: String = list.iter().map(|p| format!("{}\n", p.display())).collect();
+    if let Some(dir) = state.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(state, text);
+}
@@ -0,0 +1,98 @@
+//! Where the UI is drawn. Normally that's stdout, but when stdout is piped
+//! (e.g. `picker | xargs ...`) the UI and any spawned editor go to the
+//! controlling terminal instead, keeping stdout clean for emitted paths.
+//! Normally it's also drawn on the alternate screen, which `--no-alt-screen`
+//! turns off so the last frame stays visible after exit.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    process::Stdio,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::{
+    event::{DisableBracketedPaste, EnableBracketedPaste},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+/// Cleared once by `--no-alt-screen`, before the UI starts
+static ALT_SCREEN: AtomicBool = AtomicBool::new(true);
+
+pub enum TermOut {
+    Stdout(io::Stdout),
+    Tty(File),
+}
+
+impl Write for TermOut {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TermOut::Stdout(out) => out.write(buf),
+            TermOut::Tty(out) => out.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TermOut::Stdout(out) => out.flush(),
+            TermOut::Tty(out) => out.flush(),
+        }
+    }
+}
+
+pub fn stdout_is_terminal() -> bool {
+    io::stdout().is_terminal()
+}
+
+fn open_tty() -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open("/dev/tty")
+}
+
+/// A handle for drawing the UI.
+pub fn output() -> io::Result<TermOut> {
+    if stdout_is_terminal() {
+        Ok(TermOut::Stdout(io::stdout()))
+    } else {
+        open_tty().map(TermOut::Tty)
+    }
+}
+
+pub fn disable_alt_screen() {
+    ALT_SCREEN.store(false, Ordering::Relaxed);
+}
+
+/// Switch to the alternate screen, unless it's been disabled.
+pub fn enter_alt_screen(out: &mut impl Write) -> io::Result<()> {
+    if ALT_SCREEN.load(Ordering::Relaxed) {
+        execute!(out, EnterAlternateScreen)?;
+    }
+    Ok(())
+}
+
+/// Back to the normal screen, unless the alternate one was never used.
+pub fn leave_alt_screen(out: &mut impl Write) -> io::Result<()> {
+    if ALT_SCREEN.load(Ordering::Relaxed) {
+        execute!(out, LeaveAlternateScreen)?;
+    }
+    Ok(())
+}
+
+/// Have pastes arrive as one event rather than as a burst of keys. Editors
+/// tend to switch this off on their way out, so it's redone after each.
+pub fn enable_paste(out: &mut impl Write) -> io::Result<()> {
+    execute!(out, EnableBracketedPaste)
+}
+
+pub fn disable_paste(out: &mut impl Write) -> io::Result<()> {
+    execute!(out, DisableBracketedPaste)
+}
+
+/// Stdout for spawned editors/pagers, so they draw on the terminal even
+/// when our own stdout is a pipe.
+pub fn child_stdout() -> Stdio {
+    if stdout_is_terminal() {
+        return Stdio::inherit();
+    }
+    open_tty().map_or_else(|_| Stdio::inherit(), Stdio::from)
+}